@@ -0,0 +1,70 @@
+//! The auxiliary files latexml generates next to a result, e.g. images with `--mathimages`,
+//! `--graphicimages` or `--svg`, or the pages of `--split`, collected per job.
+use std::fs::{create_dir_all, read_dir, remove_dir, remove_dir_all};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::documents;
+
+/// Boot options with which latexml generates files next to its results
+const GENERATING_OPTIONS: [&str; 4] = ["mathimages", "graphicimages", "svg", "split"];
+
+/// Whether latexml generates files next to its results with `boot_options`, either as asked
+/// by one of `GENERATING_OPTIONS`, or by an HTML `format`, which converts graphics to images
+pub fn generated_with(boot_options: &[(String, String)]) -> bool {
+  boot_options.iter().any(|(key, value)| {
+    GENERATING_OPTIONS.contains(&key.as_str())
+      || (key == "format" && documents::format_extension(value) != "xml")
+  })
+}
+
+/// Prepares the working directory of job number `job` under `dir`, removing the files of
+/// any earlier run, and returns the destination latexml converts into, within it
+pub fn prepare(dir: &Path, job: usize, extension: &str) -> io::Result<PathBuf> {
+  let job_dir = dir.join(job.to_string());
+  if job_dir.exists() {
+    remove_dir_all(&job_dir)?;
+  }
+  create_dir_all(&job_dir)?;
+  Ok(job_dir.join(format!("{}.{}", job, extension)))
+}
+
+/// The files generated in the working directory of `destination`, other than `destination`
+/// itself, relative to `root` and sorted. Working directories left empty are removed
+pub fn collect(destination: &Path, root: &Path) -> io::Result<Vec<String>> {
+  let job_dir = match destination.parent() {
+    Some(job_dir) => job_dir,
+    None => return Ok(Vec::new()),
+  };
+  let mut files = Vec::new();
+  collect_files(job_dir, &mut files)?;
+  files.retain(|file| file != destination);
+  files.sort();
+  if read_dir(job_dir)?.next().is_none() {
+    remove_dir(job_dir)?;
+  }
+  Ok(
+    files
+      .iter()
+      .map(|file| {
+        file
+          .strip_prefix(root)
+          .unwrap_or(file)
+          .to_string_lossy()
+          .into_owned()
+      })
+      .collect(),
+  )
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+  for dir_entry in read_dir(dir)? {
+    let path = dir_entry?.path();
+    if path.is_dir() {
+      collect_files(&path, files)?;
+    } else {
+      files.push(path);
+    }
+  }
+  Ok(())
+}
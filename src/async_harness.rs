@@ -0,0 +1,136 @@
+//! An async, cloneable handle to a `Harness`, for sharing one server pool between tokio tasks.
+use std::error::Error;
+use std::result::Result;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::warn;
+
+use crate::failure::{ConversionError, FailureKind};
+use crate::metrics;
+use crate::pool::ServerPool;
+use crate::server::{LatexmlResponse, Server, Source};
+use crate::Harness;
+
+#[derive(Debug, Clone)]
+pub struct AsyncHarness {
+  harness: Arc<Harness>,
+}
+
+/// A server checked out of the pool, which is returned to the pool when dropped.
+/// If the checkout is dropped mid-conversion (e.g. the caller's future was cancelled),
+/// the server's latexmls process is terminated, as it may still be busy with the abandoned job.
+struct Checkout {
+  server: Option<Server>,
+  pool: Arc<ServerPool>,
+  in_flight: bool,
+}
+impl Checkout {
+  fn server(&mut self) -> &mut Server {
+    self.server.as_mut().unwrap()
+  }
+}
+impl Drop for Checkout {
+  fn drop(&mut self) {
+    if let Some(mut server) = self.server.take() {
+      if self.in_flight {
+        server.terminate_proc();
+      }
+      // a pool never hands out more servers than it has room for
+      let _ = self.pool.checkin(server);
+    }
+  }
+}
+
+impl From<Harness> for AsyncHarness {
+  fn from(harness: Harness) -> Self {
+    AsyncHarness {
+      harness: Arc::new(harness),
+    }
+  }
+}
+
+impl AsyncHarness {
+  /// Converts a single job with the next available server, waiting for one if all are busy.
+  /// Dropping the returned future cancels the conversion.
+  pub async fn convert(&self, job: &str) -> Result<LatexmlResponse, Box<dyn Error + Send + Sync>> {
+    let start = Instant::now();
+    // waiting for a server is async, so that dropping this future stops waiting right away
+    let server = self
+      .harness
+      .servers
+      .checkout_async(self.harness.checkout_timeout)
+      .await?;
+    let mut checkout = Checkout {
+      server: Some(server),
+      pool: self.harness.servers.clone(),
+      in_flight: false,
+    };
+    self.harness.configure(checkout.server());
+    // (re)booting a latexmls process is blocking work
+    let mut checkout = tokio::task::spawn_blocking(move || {
+      let mut checkout = checkout;
+      checkout
+        .server()
+        .ensure_server()
+        .map(|_| checkout)
+        .map_err(|e| e.to_string())
+    })
+    .await??;
+
+    let server = checkout.server();
+    let request = server.http_request(&server.source_body(Source::Literal(job), None));
+    let address = format!("127.0.0.1:{}", server.port());
+    // if the first call has an empty response, retry once, as the blocking client does
+    let mut attempt = 0;
+    let response = loop {
+      attempt += 1;
+      checkout.server().count_call();
+      checkout.in_flight = true;
+      let exchanged = match self.harness.job_timeout {
+        Some(limit) => tokio::time::timeout(limit, exchange(&address, &request))
+          .await
+          .unwrap_or_else(|_| {
+            Err(
+              ConversionError::new(
+                FailureKind::Timeout,
+                "conversion exceeded the per-job time limit",
+              )
+              .into(),
+            )
+          }),
+        None => exchange(&address, &request).await,
+      };
+      checkout.in_flight = false;
+      match exchanged {
+        Ok(response_u8) => match checkout.server().parse_response(&response_u8) {
+          Ok(payload) => break payload,
+          Err(e) if e.kind == FailureKind::EmptyResponse && attempt < 2 => continue,
+          Err(e) => {
+            checkout.server().terminate_proc();
+            return Err(e.into());
+          },
+        },
+        Err(e) => {
+          let server = checkout.server();
+          warn!(server = server.id(), port = server.port(), error = ?e, "async conversion failed");
+          server.terminate_proc();
+          return Err(e);
+        },
+      }
+    };
+    metrics::global().observe_conversion(response.status_code, start.elapsed());
+    Ok(response)
+  }
+}
+
+async fn exchange(address: &str, request: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+  let mut stream = TcpStream::connect(address).await?;
+  stream.set_nodelay(true)?;
+  stream.write_all(request.as_bytes()).await?;
+  let mut response_u8 = Vec::new();
+  stream.read_to_end(&mut response_u8).await?;
+  Ok(response_u8)
+}
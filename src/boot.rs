@@ -0,0 +1,84 @@
+//! Inspection of the boot-time initialization call of latexmls servers,
+//! which loads the boot options and preloads, so that problems with them aren't silently lost.
+use crate::server::{LatexmlResponse, Server};
+
+/// The initialization response of a single server
+#[derive(Debug, Clone)]
+pub struct ServerBoot {
+  pub server: usize,
+  pub port: u16,
+  pub response: LatexmlResponse,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BootReport {
+  pub servers: Vec<ServerBoot>,
+}
+
+impl BootReport {
+  /// Records the initialization response of a freshly booted server
+  pub fn record(&mut self, server: &Server) {
+    if let Some(response) = server.init_response() {
+      self.servers.push(ServerBoot {
+        server: server.id(),
+        port: server.port(),
+        response: response.clone(),
+      });
+    }
+  }
+
+  /// The worst status code of the initialization calls, 0 if all booted cleanly
+  pub fn status_code(&self) -> u8 {
+    self
+      .servers
+      .iter()
+      .map(|boot| boot.response.status_code)
+      .max()
+      .unwrap_or(0)
+  }
+
+  /// Whether all servers booted without warnings or errors
+  pub fn is_clean(&self) -> bool {
+    self.status_code() == 0
+  }
+
+  /// The distinct warnings, errors and fatal messages latexmls logged while booting
+  pub fn messages(&self) -> Vec<&str> {
+    let mut messages = Vec::new();
+    for boot in self.servers.iter() {
+      for message in log_messages(&boot.response.log, &["Warning:", "Error:", "Fatal:"]) {
+        if !messages.contains(&message) {
+          messages.push(message);
+        }
+      }
+    }
+    messages
+  }
+}
+
+/// Fails with the problems latexmls reported while loading the boot options and preloads,
+/// e.g. a missing preload file
+pub(crate) fn preflight_check(init: &LatexmlResponse) -> Result<(), String> {
+  if init.status_code < 2 {
+    return Ok(());
+  }
+  let problems = log_messages(&init.log, &["Error:", "Fatal:"]);
+  let details = if problems.is_empty() {
+    init.log.trim().to_string()
+  } else {
+    problems.join("\n")
+  };
+  Err(format!(
+    "latexmls failed to load its boot options ({}):\n{}",
+    init.status, details
+  ))
+}
+
+/// The lines of a latexml log reporting messages of the given levels
+fn log_messages<'a>(log: &'a str, levels: &[&str]) -> Vec<&'a str> {
+  log
+    .lines()
+    .map(str::trim)
+    .filter(|line| levels.iter().any(|level| line.starts_with(level)))
+    .collect()
+}
@@ -0,0 +1,114 @@
+//! Transparent compression of input and output files, by their extension:
+//! `.gz` for gzip, `.zst` for zstd and `.bz2` for bzip2.
+//!
+//! Compressed files are streamed through, and are never decompressed in full,
+//! neither in memory nor on disk.
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use bzip2::read::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+  None,
+  Gzip,
+  Zstd,
+  Bzip2,
+}
+
+impl Compression {
+  /// The compression of a file, as given by its extension
+  pub fn from_path(path: &str) -> Self {
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+      Some("gz") => Compression::Gzip,
+      Some("zst") => Compression::Zstd,
+      Some("bz2") => Compression::Bzip2,
+      _ => Compression::None,
+    }
+  }
+
+  /// A decompressing reader of `file`
+  pub fn reader(self, file: File) -> io::Result<Box<dyn Read + Send>> {
+    Ok(match self {
+      Compression::None => Box::new(file),
+      // concatenated members, as written by e.g. pigz, are read through
+      Compression::Gzip => Box::new(MultiGzDecoder::new(file)),
+      Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+      Compression::Bzip2 => Box::new(MultiBzDecoder::new(file)),
+    })
+  }
+
+  /// A compressing writer into `file`
+  pub fn writer(self, file: File) -> io::Result<Encoder> {
+    Ok(match self {
+      Compression::None => Encoder::None(BufWriter::new(file)),
+      Compression::Gzip => Encoder::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+      Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(file, 0)?),
+      Compression::Bzip2 => Encoder::Bzip2(BzEncoder::new(file, bzip2::Compression::default())),
+    })
+  }
+}
+
+/// The path of a file without its compression extension, e.g. `formulas.csv` for
+/// `formulas.csv.gz`, which tells the format of its contents
+pub fn strip_extension(path: &str) -> &str {
+  match Compression::from_path(path) {
+    Compression::None => path,
+    _ => &path[..path.rfind('.').unwrap_or(path.len())],
+  }
+}
+
+/// Opens a file for reading, decompressing it as per its extension
+pub fn open(path: &str) -> io::Result<Box<dyn Read + Send>> {
+  Compression::from_path(path).reader(File::open(path)?)
+}
+
+/// Creates (or truncates) a file for writing, compressing it as per its extension
+pub fn create(path: &str) -> io::Result<Encoder> {
+  Compression::from_path(path).writer(File::create(path)?)
+}
+
+/// A writer compressing into a file, which must be `finish`ed once all is written
+pub enum Encoder {
+  None(BufWriter<File>),
+  Gzip(GzEncoder<File>),
+  Zstd(zstd::Encoder<'static, File>),
+  Bzip2(BzEncoder<File>),
+}
+
+impl Encoder {
+  /// Writes the end of the compressed stream, and flushes it to the file
+  pub fn finish(self) -> io::Result<()> {
+    let mut file = match self {
+      Encoder::None(writer) => writer.into_inner().map_err(|e| e.into_error())?,
+      Encoder::Gzip(encoder) => encoder.finish()?,
+      Encoder::Zstd(encoder) => encoder.finish()?,
+      Encoder::Bzip2(encoder) => encoder.finish()?,
+    };
+    file.flush()
+  }
+}
+
+impl Write for Encoder {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match self {
+      Encoder::None(writer) => writer.write(buf),
+      Encoder::Gzip(encoder) => encoder.write(buf),
+      Encoder::Zstd(encoder) => encoder.write(buf),
+      Encoder::Bzip2(encoder) => encoder.write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      Encoder::None(writer) => writer.flush(),
+      Encoder::Gzip(encoder) => encoder.flush(),
+      Encoder::Zstd(encoder) => encoder.flush(),
+      Encoder::Bzip2(encoder) => encoder.flush(),
+    }
+  }
+}
@@ -0,0 +1,85 @@
+//! Configuration files for the CLI, in TOML or YAML, holding both runner settings
+//! and latexml boot options, keyed by the long names of their command-line flags:
+//!
+//! ```toml
+//! from_port = 3334
+//! preload = ["LaTeX.pool", "article.cls", "amsmath.sty"]
+//! whatsin = "math"
+//! whatsout = "math"
+//! pmml = true
+//! ```
+use std::error::Error;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::result::Result;
+
+use serde_json::{Map, Value};
+
+/// Flag values by flag name, in the order of the file. A `true` value stands for
+/// a flag without value, and an array for a flag repeated once per value
+pub type Config = Map<String, Value>;
+
+/// Loads a configuration file, as YAML if its extension is `yaml` or `yml`, as TOML otherwise
+pub fn load(path: &str) -> Result<Config, Box<dyn Error>> {
+  let contents = read_to_string(path).map_err(|e| format!("can't read config {}: {}", path, e))?;
+  let extension = Path::new(path).extension().and_then(|ext| ext.to_str());
+  let value: Value = match extension {
+    Some("yaml") | Some("yml") => {
+      serde_yaml::from_str(&contents).map_err(|e| format!("invalid YAML config {}: {}", path, e))?
+    },
+    _ => toml::from_str(&contents).map_err(|e| format!("invalid TOML config {}: {}", path, e))?,
+  };
+  match value {
+    Value::Object(config) => Ok(config),
+    Value::Null => Ok(Config::new()),
+    _ => Err(
+      format!(
+        "config {} should hold a table of flag names to values",
+        path
+      )
+      .into(),
+    ),
+  }
+}
+
+/// The command-line arguments equivalent to `config`, leaving out the flags for which
+/// `skip` holds, e.g. because they were also given on the command line
+pub fn to_arguments<F>(config: &Config, skip: F) -> Result<Vec<String>, Box<dyn Error>>
+where
+  F: Fn(&str) -> bool,
+{
+  let mut arguments = Vec::new();
+  for (name, value) in config.iter() {
+    if skip(name) {
+      continue;
+    }
+    let values = match value {
+      Value::Array(values) => values.iter().collect(),
+      value => vec![value],
+    };
+    for value in values {
+      match value {
+        Value::Bool(true) => arguments.push(format!("--{}", name)),
+        Value::Bool(false) => {},
+        Value::String(text) => arguments.push(format!("--{}={}", name, text)),
+        Value::Number(number) => arguments.push(format!("--{}={}", name, number)),
+        _ => return Err(format!("unsupported value for {} in config: {}", name, value).into()),
+      }
+    }
+  }
+  Ok(arguments)
+}
+
+/// The configuration value of a command-line value, as an integer if it is one,
+/// e.g. for `--from_port 3334`, or as a string otherwise
+pub fn from_argument(value: &str) -> Value {
+  match value.parse::<i64>() {
+    Ok(number) => Value::from(number),
+    Err(_) => Value::String(value.to_string()),
+  }
+}
+
+/// Renders a configuration as TOML
+pub fn render(config: &Config) -> Result<String, Box<dyn Error>> {
+  Ok(toml::to_string(config)?)
+}
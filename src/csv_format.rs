@@ -0,0 +1,267 @@
+//! The layout of CSV inputs: which column holds the TeX of each job,
+//! and which columns are copied over to the output, next to the result,
+//! as well as the CSV dialects of inputs and outputs.
+use std::error::Error;
+use std::fmt;
+use std::result::Result;
+use std::str::FromStr;
+
+use csv::{QuoteStyle, ReaderBuilder, StringRecord, Terminator, WriterBuilder};
+
+/// A CSV column, by its name in the header row, or by its 0-based index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+  Index(usize),
+  Name(String),
+}
+
+impl FromStr for Column {
+  type Err = String;
+  fn from_str(column: &str) -> Result<Self, Self::Err> {
+    match column.trim() {
+      "" => Err(String::from("empty CSV column name")),
+      column => Ok(match column.parse() {
+        Ok(index) => Column::Index(index),
+        Err(_) => Column::Name(column.to_string()),
+      }),
+    }
+  }
+}
+
+impl fmt::Display for Column {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Column::Index(index) => write!(f, "{}", index),
+      Column::Name(name) => f.write_str(name),
+    }
+  }
+}
+
+/// When fields are quoted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quoting {
+  /// Only fields containing delimiters, quotes or terminators (the default)
+  #[default]
+  Necessary,
+  /// All fields, when writing
+  Always,
+  /// Fields which aren't numbers, when writing
+  NonNumeric,
+  /// Never, quotes are read and written as any other character
+  Never,
+}
+
+impl FromStr for Quoting {
+  type Err = String;
+  fn from_str(quoting: &str) -> Result<Self, Self::Err> {
+    match quoting {
+      "necessary" => Ok(Quoting::Necessary),
+      "always" => Ok(Quoting::Always),
+      "non_numeric" => Ok(Quoting::NonNumeric),
+      "never" => Ok(Quoting::Never),
+      other => Err(format!(
+        "unknown CSV quoting {:?}, expected one of: necessary, always, non_numeric, never",
+        other
+      )),
+    }
+  }
+}
+
+/// The punctuation of a CSV file
+#[derive(Debug, Clone)]
+pub struct CsvDialect {
+  pub delimiter: u8,
+  pub quote: u8,
+  pub quoting: Quoting,
+  /// The character escaping quotes inside quoted fields.
+  /// If `None` (the default), quotes are escaped by doubling them
+  pub escape: Option<u8>,
+  /// The record terminator. If `None` (the default), any of CRLF, CR or LF when reading,
+  /// and LF when writing
+  pub terminator: Option<Terminator>,
+}
+
+impl Default for CsvDialect {
+  fn default() -> Self {
+    CsvDialect {
+      delimiter: b',',
+      quote: b'"',
+      quoting: Quoting::Necessary,
+      escape: None,
+      terminator: None,
+    }
+  }
+}
+
+impl CsvDialect {
+  /// A reader of this dialect
+  pub fn reader_builder(&self) -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder
+      .delimiter(self.delimiter)
+      .quote(self.quote)
+      .quoting(self.quoting != Quoting::Never)
+      .escape(self.escape)
+      .double_quote(self.escape.is_none());
+    if let Some(terminator) = self.terminator {
+      builder.terminator(terminator);
+    }
+    builder
+  }
+
+  /// A writer of this dialect
+  pub fn writer_builder(&self) -> WriterBuilder {
+    let mut builder = WriterBuilder::new();
+    builder
+      .delimiter(self.delimiter)
+      .quote(self.quote)
+      .quote_style(match self.quoting {
+        Quoting::Necessary => QuoteStyle::Necessary,
+        Quoting::Always => QuoteStyle::Always,
+        Quoting::NonNumeric => QuoteStyle::NonNumeric,
+        Quoting::Never => QuoteStyle::Never,
+      })
+      .double_quote(self.escape.is_none());
+    if let Some(escape) = self.escape {
+      builder.escape(escape);
+    }
+    if let Some(terminator) = self.terminator {
+      builder.terminator(terminator);
+    }
+    builder
+  }
+}
+
+/// Parses a single-byte CSV character, given literally, as one of the escapes
+/// `\t`, `\n`, `\r`, `\0`, or by the names `tab` and `space`
+pub fn parse_char(text: &str) -> Result<u8, String> {
+  match text {
+    "\\t" | "tab" => Ok(b'\t'),
+    "\\n" => Ok(b'\n'),
+    "\\r" => Ok(b'\r'),
+    "\\0" => Ok(b'\0'),
+    "space" => Ok(b' '),
+    text if text.len() == 1 => Ok(text.as_bytes()[0]),
+    other => Err(format!(
+      "expected a single ASCII character for CSV punctuation, got {:?}",
+      other
+    )),
+  }
+}
+
+/// Parses a CSV record terminator, either `crlf` or a single-byte character as per `parse_char`
+pub fn parse_terminator(text: &str) -> Result<Terminator, String> {
+  match text {
+    "crlf" => Ok(Terminator::CRLF),
+    text => parse_char(text).map(Terminator::Any),
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CsvOptions {
+  /// The dialect of CSV inputs (and of the failures files written for them)
+  pub input: CsvDialect,
+  /// The dialect of CSV outputs
+  pub output: CsvDialect,
+  /// Whether to start outputs with a row naming their columns
+  pub output_headers: bool,
+  /// Whether the first row of the input names its columns
+  pub has_headers: bool,
+  /// The column holding the TeX of each job, the first one if `None` (the default)
+  pub tex_column: Option<Column>,
+  /// Columns copied from each input row to its output row, before the result
+  pub passthrough_columns: Vec<Column>,
+}
+
+/// The columns of `CsvOptions`, resolved to indices for a given input
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedColumns {
+  pub tex: usize,
+  pub passthrough: Vec<usize>,
+}
+
+impl CsvOptions {
+  /// A reader for inputs with these options
+  pub fn reader_builder(&self) -> ReaderBuilder {
+    let mut builder = self.input.reader_builder();
+    builder.has_headers(self.has_headers);
+    builder
+  }
+
+  /// Resolves the selected columns to indices, given the header row of the input, if any
+  pub fn resolve(&self, headers: Option<&StringRecord>) -> Result<ResolvedColumns, Box<dyn Error>> {
+    let index_of = |column: &Column| -> Result<usize, Box<dyn Error>> {
+      match column {
+        Column::Index(index) => match headers {
+          Some(headers) if *index >= headers.len() => Err(
+            format!(
+              "CSV column {} is out of range, the header row has {} columns",
+              index,
+              headers.len()
+            )
+            .into(),
+          ),
+          _ => Ok(*index),
+        },
+        Column::Name(name) => match headers {
+          Some(headers) => headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| format!("no CSV column named {:?} in the header row", name).into()),
+          None => Err(
+            format!(
+              "CSV column {:?} is selected by name, without a header row",
+              name
+            )
+            .into(),
+          ),
+        },
+      }
+    };
+    Ok(ResolvedColumns {
+      tex: match self.tex_column {
+        Some(ref column) => index_of(column)?,
+        None => 0,
+      },
+      passthrough: self
+        .passthrough_columns
+        .iter()
+        .map(index_of)
+        .collect::<Result<_, _>>()?,
+    })
+  }
+}
+
+impl ResolvedColumns {
+  /// The TeX input of a row, `None` if the row is too short to hold it
+  pub fn tex<'r>(&self, record: &'r StringRecord) -> Option<&'r str> {
+    record.get(self.tex)
+  }
+
+  /// The output row for an input row, with its passthrough columns followed by the `result`
+  pub fn output_row<'r>(&self, record: &'r StringRecord, result: &'r str) -> Vec<&'r str> {
+    let mut row: Vec<&str> = self
+      .passthrough
+      .iter()
+      .map(|index| record.get(*index).unwrap_or_default())
+      .collect();
+    row.push(result);
+    row
+  }
+
+  /// The header row of outputs, for inputs with `headers`, if any:
+  /// the passthrough columns, followed by `result`
+  pub fn output_headers(&self, headers: Option<&StringRecord>) -> Vec<String> {
+    let mut row: Vec<String> = self
+      .passthrough
+      .iter()
+      .map(|index| {
+        headers
+          .and_then(|headers| headers.get(*index))
+          .map_or_else(|| index.to_string(), str::to_string)
+      })
+      .collect();
+    row.push(String::from("result"));
+    row
+  }
+}
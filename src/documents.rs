@@ -0,0 +1,35 @@
+//! Document-mode conversion: whole `.tex` documents on disk, converted as file sources,
+//! rather than literal TeX strings.
+use std::fs::read_dir;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The extension of the documents latexmls writes in a given `--format`,
+/// `xml` if unknown
+pub fn format_extension(format: &str) -> &'static str {
+  match format {
+    "html" | "html4" | "html5" => "html",
+    "xhtml" => "xhtml",
+    _ => "xml",
+  }
+}
+
+/// The `.tex` files of `dir` and of its subdirectories, sorted by path
+pub fn find_documents(dir: &Path) -> io::Result<Vec<PathBuf>> {
+  let mut documents = Vec::new();
+  collect_documents(dir, &mut documents)?;
+  documents.sort();
+  Ok(documents)
+}
+
+fn collect_documents(dir: &Path, documents: &mut Vec<PathBuf>) -> io::Result<()> {
+  for dir_entry in read_dir(dir)? {
+    let path = dir_entry?.path();
+    if path.is_dir() {
+      collect_documents(&path, documents)?;
+    } else if path.extension().and_then(|ext| ext.to_str()) == Some("tex") {
+      documents.push(path);
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,164 @@
+//! Classification of conversion failures, which never produced a latexmls response.
+//!
+//! Each class is recorded with its own status code, above the codes used by latexml itself
+//! (0 ok, 1 warning, 2 error, 3 fatal), so that logs record *why* a job failed.
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailureKind {
+  /// The conversion did not finish in time
+  Timeout,
+  /// latexmls did not accept connections, e.g. because it failed to boot
+  ConnectionRefused,
+  /// The connection to latexmls broke mid-conversion, e.g. because the process died
+  ConnectionLost,
+  /// latexmls closed the connection without responding
+  EmptyResponse,
+  /// latexmls responded with a payload that isn't the expected JSON
+  MalformedResponse,
+  /// No server became available to convert the job
+  Unavailable,
+  /// Any other failure
+  Other,
+  /// The job repeatedly took down servers, and was quarantined rather than retried
+  Quarantined,
+  /// The job couldn't be read from the input, e.g. a malformed CSV row, and wasn't converted
+  MalformedInput,
+}
+
+pub const ALL_FAILURE_KINDS: [FailureKind; 9] = [
+  FailureKind::Timeout,
+  FailureKind::ConnectionRefused,
+  FailureKind::ConnectionLost,
+  FailureKind::EmptyResponse,
+  FailureKind::MalformedResponse,
+  FailureKind::Unavailable,
+  FailureKind::Other,
+  FailureKind::Quarantined,
+  FailureKind::MalformedInput,
+];
+
+impl FailureKind {
+  /// Classifies an error returned by a conversion attempt
+  pub fn classify(error: &(dyn Error + 'static)) -> Self {
+    if let Some(conversion_error) = error.downcast_ref::<ConversionError>() {
+      conversion_error.kind
+    } else if let Some(io_error) = error.downcast_ref::<io::Error>() {
+      match io_error.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => FailureKind::Timeout,
+        io::ErrorKind::ConnectionRefused => FailureKind::ConnectionRefused,
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::UnexpectedEof => FailureKind::ConnectionLost,
+        _ => FailureKind::Other,
+      }
+    } else if error.is::<serde_json::Error>() {
+      FailureKind::MalformedResponse
+    } else {
+      FailureKind::Other
+    }
+  }
+
+  /// The status code recorded for jobs failing with this kind
+  pub fn status_code(self) -> u8 {
+    match self {
+      FailureKind::Timeout => 4,
+      FailureKind::ConnectionRefused => 5,
+      FailureKind::ConnectionLost => 6,
+      FailureKind::EmptyResponse => 7,
+      FailureKind::MalformedResponse => 8,
+      FailureKind::Unavailable => 9,
+      FailureKind::Other => 10,
+      FailureKind::Quarantined => 11,
+      FailureKind::MalformedInput => 12,
+    }
+  }
+
+  /// Whether the failure happened while latexmls was converting the job,
+  /// i.e. the job itself may have taken down the server
+  pub fn during_conversion(self) -> bool {
+    matches!(
+      self,
+      FailureKind::Timeout
+        | FailureKind::ConnectionLost
+        | FailureKind::EmptyResponse
+        | FailureKind::MalformedResponse
+    )
+  }
+
+  /// Whether the job itself couldn't be read from the input,
+  /// so that converting it again can't succeed
+  pub fn in_input(self) -> bool {
+    matches!(self, FailureKind::MalformedInput)
+  }
+
+  /// The kind recorded with `status_code`, if it is one of the runner's failure codes
+  pub fn from_status_code(status_code: u8) -> Option<Self> {
+    ALL_FAILURE_KINDS
+      .iter()
+      .find(|kind| kind.status_code() == status_code)
+      .copied()
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      FailureKind::Timeout => "timeout",
+      FailureKind::ConnectionRefused => "connection_refused",
+      FailureKind::ConnectionLost => "connection_lost",
+      FailureKind::EmptyResponse => "empty_response",
+      FailureKind::MalformedResponse => "malformed_response",
+      FailureKind::Unavailable => "unavailable",
+      FailureKind::Other => "other",
+      FailureKind::Quarantined => "quarantined",
+      FailureKind::MalformedInput => "malformed_input",
+    }
+  }
+}
+
+impl fmt::Display for FailureKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
+impl FromStr for FailureKind {
+  type Err = String;
+  fn from_str(name: &str) -> Result<Self, Self::Err> {
+    ALL_FAILURE_KINDS
+      .iter()
+      .find(|kind| kind.name() == name)
+      .copied()
+      .ok_or_else(|| {
+        format!(
+          "unknown failure kind {:?}, expected one of: {}",
+          name,
+          ALL_FAILURE_KINDS.map(|kind| kind.name()).join(", ")
+        )
+      })
+  }
+}
+
+/// A conversion failure detected by the runner itself, rather than reported by the OS
+#[derive(Debug, Clone)]
+pub struct ConversionError {
+  pub kind: FailureKind,
+  pub message: String,
+}
+impl ConversionError {
+  pub fn new(kind: FailureKind, message: impl Into<String>) -> Self {
+    ConversionError {
+      kind,
+      message: message.into(),
+    }
+  }
+}
+impl fmt::Display for ConversionError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.kind, self.message)
+  }
+}
+impl Error for ConversionError {}
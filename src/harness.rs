@@ -1,26 +1,82 @@
-use crate::server::{LatexmlResponse, Server};
+use crate::assets;
+use crate::boot::{self, BootReport};
+use crate::compression::{self, Compression, Encoder};
+use crate::csv_format::{CsvOptions, ResolvedColumns};
+use crate::documents;
+use crate::failure::FailureKind;
+use crate::metrics;
+use crate::pool::{PoolError, ServerPool};
+use crate::quarantine::Quarantine;
+use crate::retry::RetryPolicy;
+use crate::server::{LatexmlResponse, Server, Source};
+use crate::txt_format::{self, MalformedLine, TxtOptions};
 
 // use std::process::{Command};
 use std::error::Error;
 use std::fs::File;
-use std::fs::{create_dir_all, read_dir};
-use std::io::{BufRead, BufReader};
+use std::fs::{create_dir_all, read_dir, remove_file, rename, write};
+use std::io::{self, BufReader, Write};
 use std::path::Path;
 use std::process;
 use std::result::Result;
 use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crossbeam::queue::ArrayQueue;
-use csv::{ReaderBuilder, Writer, WriterBuilder};
+use csv::{ReaderBuilder, StringRecord, Writer, WriterBuilder};
 use itertools::Itertools;
 use rayon::prelude::*;
+use tracing::{error, info, warn};
 use which::which;
 
 #[derive(Debug)]
 pub struct Harness {
   pub from_port: u16,
   pub batch_size: usize,
-  servers: Arc<ArrayQueue<Server>>,
+  /// How long a conversion may wait for a free server, before giving up.
+  /// Waits indefinitely if `None` (the default)
+  pub checkout_timeout: Option<Duration>,
+  /// How failed conversion jobs are retried
+  pub retry_policy: RetryPolicy,
+  /// If set, jobs that repeatedly take down servers are quarantined rather than retried
+  pub quarantine: Option<Quarantine>,
+  /// If set, jobs with a status code above `failure_threshold` are also written to this file,
+  /// in the same format as their input (or into a file named as the input, in this directory,
+  /// when converting directories), for re-running via `rerun_failures`.
+  /// Malformed input rows are left out, as re-running them can't succeed
+  pub failures_file: Option<String>,
+  /// The highest status code of a successful job, 2 (error) by default
+  pub failure_threshold: u8,
+  /// If set, conversions running longer than this are abandoned by the runner,
+  /// which kills and respawns their server, failing the job with a `timeout`
+  pub job_timeout: Option<Duration>,
+  /// If set, servers whose latexmls processes grow beyond this resident memory, in bytes,
+  /// are recycled before their next conversion
+  pub max_server_memory: Option<u64>,
+  /// If set, servers running for longer than this are recycled before their next conversion
+  pub max_server_age: Option<Duration>,
+  /// Whether servers boot their replacement in the background as they near
+  /// `autoflush` or their other recycling limits, rather than when reaching them
+  pub warm_standby: bool,
+  /// The layout of CSV inputs, and the columns copied to the output
+  pub csv: CsvOptions,
+  /// The encoding and framing of TXT inputs
+  pub txt: TxtOptions,
+  /// If set, each literal job converts into its own directory under this one, e.g. `17/` for
+  /// the job at line 17, where latexml writes the files it generates next to the result, such as
+  /// images with `--mathimages` or `--svg`. The generated files are listed in an extra `assets`
+  /// column of the output, relative to this directory. `convert_dir` uses a subdirectory
+  /// per input file, and `rerun_failures` one named as the failures file
+  pub assets_dir: Option<String>,
+  /// The extension of the outputs of `convert_documents`, and of the files literal jobs
+  /// convert into with an `assets_dir`,
+  /// as given by the `format` boot option, `xml` by default
+  pub document_extension: String,
+  /// Whether jobs may generate files, as per the boot options, e.g. `--mathimages`.
+  /// Only then do jobs convert into directories of their own under the `assets_dir`
+  pub generates_assets: bool,
+  boot_report: BootReport,
+  pub(crate) servers: Arc<ServerPool>,
 }
 
 impl Harness {
@@ -33,45 +89,94 @@ impl Harness {
     autoflush: usize,
     boot_options: Vec<(String, String)>,
   ) -> Result<Self, Box<dyn Error>> {
-    let latexmls_which = which("latexmls").expect("latexmls needs to be installed and visible");
+    let latexmls_which = which("latexmls")
+      .map_err(|e| format!("latexmls needs to be installed and visible: {}", e))?;
     let latexmls_exec = latexmls_which.as_path().to_string_lossy().to_string();
     let thread_count = rayon::current_num_threads();
-    let servers = Arc::new(ArrayQueue::new(thread_count));
-    (from_port..from_port + thread_count as u16)
+    let cache_key = format!("latexml_runner:{}", process::id());
+    // preflight: boot a single server first, so that bad options or preloads
+    // are reported once, precisely, before spinning up the full pool
+    let first = Server::boot_at(
+      latexmls_exec.clone(),
+      from_port,
+      autoflush,
+      cache_key.clone(),
+      boot_options.clone(),
+    )
+    .map_err(|e| {
+      format!(
+        "failed to boot a latexmls server at port {}, check your installation and options: {}",
+        from_port, e
+      )
+    })?;
+    if let Some(init) = first.init_response() {
+      boot::preflight_check(init)?;
+    }
+    let mut boot_report = BootReport::default();
+    boot_report.record(&first);
+    let servers = Arc::new(ServerPool::new(thread_count));
+    servers.add(first)?;
+    let document_extension = documents::format_extension(
+      boot_options
+        .iter()
+        .rev()
+        .find(|(key, _)| key == "format")
+        .map_or("", |(_, format)| format.as_str()),
+    )
+    .to_string();
+    let generates_assets = assets::generated_with(&boot_options);
+    let booted: Vec<Result<Server, String>> = (from_port + 1..from_port + thread_count as u16)
       .into_par_iter()
-      .for_each(|port| {
-        servers
-          .clone()
-          .push(
-            Server::boot_at(
-              latexmls_exec.to_string(),
-              port,
-              autoflush,
-              format!("latexml_runner:{}", process::id()),
-              boot_options.clone(),
-            )
-            .unwrap_or_else(|e| {
-              panic!(
-                "failed to init first latexmls servers from port {}, check your installation: {:?}",
-                port, e
-              )
-            }),
-          )
-          .expect("failed to initialize server ArrayQueue");
-      });
+      .map(|port| {
+        Server::boot_at(
+          latexmls_exec.to_string(),
+          port,
+          autoflush,
+          cache_key.clone(),
+          boot_options.clone(),
+        )
+        .map_err(|e| format!("failed to boot a latexmls server at port {}: {}", port, e))
+      })
+      .collect();
+    for server in booted {
+      let server = server?;
+      boot_report.record(&server);
+      servers.add(server)?;
+    }
     Ok(Harness {
       from_port,
       // Let's both fit in RAM and also maximally utilize the CPUs
       // without artificial round-robin bottlenecks (batch_size=cpus)
       batch_size: (100 * thread_count),
+      checkout_timeout: None,
+      retry_policy: RetryPolicy::default(),
+      quarantine: None,
+      failures_file: None,
+      failure_threshold: 2,
+      job_timeout: None,
+      max_server_memory: None,
+      max_server_age: None,
+      warm_standby: false,
+      csv: CsvOptions::default(),
+      txt: TxtOptions::default(),
+      assets_dir: None,
+      document_extension,
+      generates_assets,
+      boot_report,
       servers,
     })
   }
 
+  /// The responses of the servers to their boot-time initialization call,
+  /// which loaded the boot options and preloads
+  pub fn boot_report(&self) -> &BootReport {
+    &self.boot_report
+  }
+
   /// Converts a (flat) directory of CSV files,
   /// each file of which is processed as per `convert_file`
   pub fn convert_dir(
-    &mut self,
+    &self,
     input_dir: &str,
     output_dir: &str,
     log_dir: &str,
@@ -90,11 +195,21 @@ impl Harness {
     for dir_entry in read_dir(input_path)?.flatten() {
       let filename = dir_entry.file_name();
       let entry = filename.to_string_lossy();
-      if entry.ends_with(".csv") {
-        self.convert_file(
+      if compression::strip_extension(&entry).ends_with(".csv") {
+        let failures_file = self
+          .failures_file
+          .as_ref()
+          .map(|failures_dir| format!("{}/{}", failures_dir, entry));
+        let assets = self
+          .assets_dir
+          .as_ref()
+          .map(|assets_dir| Path::new(assets_dir).join(&*entry));
+        self.file_conversion(
           &format!("{}/{}", input_dir, entry),
           &format!("{}/result_{}", output_dir, entry),
           &format!("{}/{}.log", log_dir, entry),
+          failures_file.as_deref(),
+          assets.as_deref(),
         )?;
       }
     }
@@ -102,13 +217,92 @@ impl Harness {
     Ok(())
   }
 
+  /// Converts the `.tex` documents of `input_dir`, and of its subdirectories, each as a file
+  /// source, with its own directory as base and search path. Their results are written into
+  /// a mirrored tree under `output_dir`, with the `document_extension`, and the status code
+  /// of each document is logged as a CSV row of its relative path and status.
+  /// Documents without a result, e.g. after a fatal error, get no output file.
+  ///
+  /// Failed documents are only recorded in the log, the `failures_file` is not written.
+  /// Poison documents are still quarantined, but recorded with their 1-based position
+  /// in path order, rather than a line number, and with their path as input.
+  pub fn convert_documents(
+    &self,
+    input_dir: &str,
+    output_dir: &str,
+    log_file: &str,
+  ) -> Result<(), Box<dyn Error>> {
+    let input_path = Path::new(input_dir);
+    if !input_path.is_dir() {
+      return Err(
+        format!(
+          "Harness::convert_documents should only ever be called on existing directories: {}",
+          input_dir
+        )
+        .into(),
+      );
+    };
+    // latexmls resolves relative paths against the working directory it inherited at boot,
+    // which needn't be the current one, so documents are sent by absolute path
+    let input_root = input_path.canonicalize()?;
+    let documents = documents::find_documents(&input_root)?;
+    info!(
+      input_dir,
+      documents = documents.len(),
+      "converting documents"
+    );
+    let output_root = Path::new(output_dir);
+    let mut log_writer = WriterBuilder::new().from_writer(create_file(log_file)?);
+    let mut progress_count = 1;
+    for batch in documents.chunks(self.batch_size) {
+      info!(
+        job = progress_count,
+        batch_size = batch.len(),
+        "converting batch"
+      );
+      // generated files, e.g. images, are written next to the outputs
+      let mut outputs = Vec::with_capacity(batch.len());
+      for document in batch.iter() {
+        let output = output_root
+          .join(document.strip_prefix(&input_root)?)
+          .with_extension(&self.document_extension);
+        if let Some(dir) = output.parent() {
+          create_dir_all(dir)?;
+        }
+        outputs.push(output);
+      }
+      let results = self.convert_sources(batch.iter().zip(outputs.iter()).enumerate().map(
+        |(index, (document, output))| {
+          (
+            progress_count + index,
+            Source::Document(document),
+            Some(output.as_path()),
+          )
+        },
+      ))?;
+      progress_count += batch.len();
+      for ((document, output), response) in batch.iter().zip(outputs.iter()).zip(results) {
+        let relative = document.strip_prefix(&input_root)?;
+        if !response.result.is_empty() {
+          write(output, &response.result)?;
+        }
+        log_writer.write_record([
+          relative.to_string_lossy().as_ref(),
+          &response.status_code.to_string(),
+        ])?;
+      }
+      log_writer.flush()?;
+    }
+    finish(log_writer)
+  }
+
   /// common setup steps for both txt and csv conversions
   pub fn setup_conversion_io(
     &self,
     input_file: &str,
     output_file: &str,
     log_file: &str,
-  ) -> Result<(Writer<File>, Writer<File>), Box<dyn Error>> {
+  ) -> Result<(Writer<Encoder>, Writer<Encoder>), Box<dyn Error>> {
     let input_path = Path::new(input_file);
     let input_dir = if input_path.is_dir() || !input_path.exists() {
       return Err(
@@ -142,76 +336,131 @@ impl Harness {
     if !log_dir.exists() {
       create_dir_all(log_dir)?;
     }
-    let out_writer = WriterBuilder::new().from_path(output_file)?;
-    let log_writer = WriterBuilder::new().from_path(log_file)?;
+    // compressed as per their extensions
+    let out_writer = self
+      .csv
+      .output
+      .writer_builder()
+      .from_writer(compression::create(output_file)?);
+    let log_writer = WriterBuilder::new().from_writer(compression::create(log_file)?);
     Ok((out_writer, log_writer))
   }
 
   /// Converts a file, dispatching to CSV or TXT readers as requested
   pub fn convert_file(
-    &mut self,
+    &self,
     input_file: &str,
     output_file: &str,
     log_file: &str,
   ) -> Result<(), Box<dyn Error>> {
-    match Path::new(input_file).extension() {
-      Some(ext) => {
-        if ext.to_str() == Some("txt") {
-          self.convert_txt_file(input_file, output_file, log_file)
-        } else {
-          self.convert_csv_file(input_file, output_file, log_file)
-        }
-      }
-      None => self.convert_csv_file(input_file, output_file, log_file),
+    self.file_conversion(
+      input_file,
+      output_file,
+      log_file,
+      self.failures_file.as_deref(),
+      self.assets_dir.as_deref().map(Path::new),
+    )
+  }
+
+  fn file_conversion(
+    &self,
+    input_file: &str,
+    output_file: &str,
+    log_file: &str,
+    failures_file: Option<&str>,
+    assets: Option<&Path>,
+  ) -> Result<(), Box<dyn Error>> {
+    if is_txt(input_file) {
+      self.txt_conversion(input_file, output_file, log_file, failures_file, assets)
+    } else {
+      self.csv_conversion(input_file, output_file, log_file, failures_file, assets)
     }
   }
 
-  /// Converts a .txt file containing one TeX input string per line.
-  /// NO multi-line formulas are supported.
+  /// Converts a .txt file containing one TeX input string per line,
+  /// or per multi-line record, as framed by `txt.framing`.
   /// Creates a CSV and log files with respective results and status codes
-  /// in the same line order as the input.
+  /// in the same record order as the input.
   pub fn convert_txt_file(
-    &mut self,
+    &self,
+    input_file: &str,
+    output_file: &str,
+    log_file: &str,
+  ) -> Result<(), Box<dyn Error>> {
+    self.txt_conversion(
+      input_file,
+      output_file,
+      log_file,
+      self.failures_file.as_deref(),
+      self.assets_dir.as_deref().map(Path::new),
+    )
+  }
+
+  fn txt_conversion(
+    &self,
     input_file: &str,
     output_file: &str,
     log_file: &str,
+    failures_file: Option<&str>,
+    assets: Option<&Path>,
   ) -> Result<(), Box<dyn Error>> {
     let (mut out_writer, mut log_writer) =
       self.setup_conversion_io(input_file, output_file, log_file)?;
+    let mut failures_writer = match failures_file {
+      Some(path) => Some(create_file(path)?),
+      None => None,
+    };
+    if self.csv.output_headers {
+      out_writer.write_record(output_headers(
+        ResolvedColumns::default().output_headers(None),
+        assets,
+      ))?;
+    }
 
-    let reader = BufReader::with_capacity(self.batch_size, File::open(input_file)?);
+    let reader = BufReader::with_capacity(self.batch_size, compression::open(input_file)?);
 
-    // Each line of the input file represents a separate conversion job.
-    // we stream it in line by line, allocating large enough batches in RAM
-    // to process in parallel
-    let batched_record_iter = reader
-      .lines()
-      .into_iter()
-      .map(|result| result.unwrap_or_else(|_| String::from("IOERROR")))
-      .chunks(self.batch_size);
+    // Each record of the input file represents a separate conversion job.
+    // we stream it in record by record, allocating large enough batches in RAM
+    // to process in parallel.
+    // Records which can't be decoded are kept in place, with a `malformed_input` status
+    let batched_record_iter = txt_format::records(reader, self.txt.clone()).chunks(self.batch_size);
     let mut progress_count = 1;
     for batch in batched_record_iter.into_iter() {
-      let chunk_data: Vec<_> = batch.collect();
+      let chunk_data: Vec<_> = batch
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("can't read {}: {}", input_file, e))?;
       let b_len = chunk_data.len();
-      eprintln!("-- converting batch, starting at job #{}", progress_count);
+      info!(job = progress_count, batch_size = b_len, "converting batch");
+      let results = self.convert_rows(
+        &chunk_data,
+        |_, record| (record.line, record.text.as_str()),
+        malformed_line,
+        assets,
+      )?;
       progress_count += b_len;
-      let results = self.convert_iterator(chunk_data.iter().map(|line| line.as_str()));
-      // We must always ensure we match inputs with outputs, or large streams become corrupted
-      let r_len = results.len();
-      assert_eq!(
-        r_len, b_len,
-        "panic: we got {} results for {} inputs!",
-        r_len, b_len
-      );
 
       // Flush this batch to output files
+      if let Some(ref mut failures) = failures_writer {
+        for (row, response) in chunk_data.iter().zip(results.iter()) {
+          if let (Ok(record), true) = (row, self.is_rerunnable(response.status_code)) {
+            self.txt.write_record(failures, &record.text)?;
+          }
+        }
+        failures.flush()?;
+      }
       for response in results.into_iter() {
-        out_writer.write_record(&[response.result])?;
+        let listed = listed_assets(&response, assets);
+        out_writer.write_record(with_assets(vec![&response.result], listed.as_deref()))?;
         log_writer.write_record(&[response.status_code.to_string()])?;
       }
       out_writer.flush()?;
       log_writer.flush()?;
     }
+    finish(out_writer)?;
+    finish(log_writer)?;
+    if let Some(failures) = failures_writer {
+      failures.finish()?;
+    }
     Ok(())
   }
 
@@ -220,26 +469,66 @@ impl Harness {
   /// Creates a CSV and log files with respective results and status codes
   /// in the same line order as the input.
   pub fn convert_csv_file(
-    &mut self,
+    &self,
+    input_file: &str,
+    output_file: &str,
+    log_file: &str,
+  ) -> Result<(), Box<dyn Error>> {
+    self.csv_conversion(
+      input_file,
+      output_file,
+      log_file,
+      self.failures_file.as_deref(),
+      self.assets_dir.as_deref().map(Path::new),
+    )
+  }
+
+  fn csv_conversion(
+    &self,
     input_file: &str,
     output_file: &str,
     log_file: &str,
+    failures_file: Option<&str>,
+    assets: Option<&Path>,
   ) -> Result<(), Box<dyn Error>> {
+    let mut reader = self.csv.reader_builder().from_reader(
+      compression::open(input_file).map_err(|e| format!("can't read {}: {}", input_file, e))?,
+    );
+    let headers = if self.csv.has_headers {
+      Some(reader.headers()?.clone())
+    } else {
+      None
+    };
+    // validate the selected columns before truncating any existing outputs
+    let columns = self.csv.resolve(headers.as_ref())?;
+
     let (mut out_writer, mut log_writer) =
       self.setup_conversion_io(input_file, output_file, log_file)?;
-
-    let mut reader = ReaderBuilder::new()
-      .has_headers(false)
-      .from_path(input_file)?;
+    let mut failures_writer = match failures_file {
+      Some(path) => Some(
+        self
+          .csv
+          .input
+          .writer_builder()
+          .from_writer(create_file(path)?),
+      ),
+      None => None,
+    };
+    if self.csv.output_headers {
+      out_writer.write_record(output_headers(
+        columns.output_headers(headers.as_ref()),
+        assets,
+      ))?;
+    }
+    if let (Some(failures), Some(headers)) = (failures_writer.as_mut(), headers.as_ref()) {
+      // so that the failures file is read back with the same options
+      failures.write_record(headers)?;
+    }
 
     // Each line of the input file represents a separate conversion job.
     // we stream it in line by line, allocating large enough batches in RAM
     // to process in parallel
-    let batched_record_iter = reader
-      .records()
-      .filter(|record| record.is_ok())
-      .map(|record| record.unwrap())
-      .chunks(self.batch_size);
+    let batched_record_iter = reader.records().chunks(self.batch_size);
 
     // we can't chunk in the generic function, since mapping each data item to &str is specific to
     // the reader in this case our CSV reader allows for `as_slice`, but if we were reading from
@@ -247,86 +536,652 @@ impl Harness {
     //
     // Similarly we can't map to &str before we collect the chunks into a vec,
     // as Rust wants to have a solid grasp on the owned data before it allows us to borrow from it.
+    //
+    // Malformed rows are kept in place, as placeholder rows with a `malformed_input` status,
+    // so that outputs stay aligned with their inputs.
     let mut progress_count = 1;
     for batch in batched_record_iter.into_iter() {
       let chunk_data: Vec<_> = batch.collect();
+      if let Some(Err(e)) = chunk_data
+        .iter()
+        .find(|row| matches!(row, Err(e) if e.is_io_error()))
+      {
+        return Err(format!("can't read {}: {}", input_file, e).into());
+      }
       let b_len = chunk_data.len();
-      eprintln!("-- converting batch, starting at job #{}", progress_count);
+      info!(job = progress_count, batch_size = b_len, "converting batch");
+      // rows too short to hold the TeX column are as malformed as those the reader rejected
+      let jobs: Vec<Result<(usize, &str), LatexmlResponse>> = chunk_data
+        .iter()
+        .map(|row| match row {
+          Ok(record) => {
+            let line = record
+              .position()
+              .map_or(0, |position| position.line() as usize);
+            columns
+              .tex(record)
+              .map(|tex| (line, tex))
+              .ok_or_else(|| short_row(line, record.len(), columns.tex))
+          },
+          Err(e) => Err(malformed_row(e)),
+        })
+        .collect();
+      let results = self.convert_rows(&jobs, |_, job| *job, Clone::clone, assets)?;
       progress_count += b_len;
-      let results = self.convert_iterator(chunk_data.iter().map(|x| x.as_slice()));
-      // We must always ensure we match inputs with outputs, or large streams become corrupted
-      let r_len = results.len();
-      assert_eq!(
-        r_len, b_len,
-        "panic: we got {} results for {} inputs!",
-        r_len, b_len
-      );
 
       // Flush this batch to output files
-      for response in results.into_iter() {
-        out_writer.write_record(&[response.result])?;
+      if let Some(ref mut failures) = failures_writer {
+        for (row, response) in chunk_data.iter().zip(results.iter()) {
+          if let (Ok(record), true) = (row, self.is_rerunnable(response.status_code)) {
+            failures.write_record(record)?;
+          }
+        }
+        failures.flush()?;
+      }
+      let placeholder = StringRecord::new();
+      for (row, response) in chunk_data.iter().zip(results) {
+        let record = row.as_ref().unwrap_or(&placeholder);
+        let listed = listed_assets(&response, assets);
+        out_writer.write_record(with_assets(
+          columns.output_row(record, &response.result),
+          listed.as_deref(),
+        ))?;
         log_writer.write_record(&[response.status_code.to_string()])?;
       }
       out_writer.flush()?;
       log_writer.flush()?;
     }
+    finish(out_writer)?;
+    finish(log_writer)?;
+    if let Some(failures) = failures_writer {
+      finish(failures)?;
+    }
+    Ok(())
+  }
+
+  /// Converts the jobs of a failures file, as written for a previous run via `failures_file`,
+  /// and merges their results back into the output and log files of that run, in place.
+  /// The n-th job of the failures file replaces the n-th row of the original run
+  /// with a status code above `failure_threshold`.
+  /// The failures file is then replaced by the jobs which failed again, ready for another re-run.
+  pub fn rerun_failures(
+    &self,
+    failures_file: &str,
+    output_file: &str,
+    log_file: &str,
+  ) -> Result<(), Box<dyn Error>> {
+    let rerun_output = format!("{}.rerun", output_file);
+    let rerun_log = format!("{}.rerun", log_file);
+    // the re-run jobs are numbered anew, so their assets go into a directory of their own
+    let assets = self.assets_dir.as_ref().map(|assets_dir| {
+      Path::new(assets_dir).join(
+        Path::new(failures_file)
+          .file_name()
+          .unwrap_or_else(|| "rerun".as_ref()),
+      )
+    });
+    // keeps the extension of the failures file, which determines its format and compression
+    let path = Path::new(failures_file);
+    let still_failing = path
+      .with_file_name(format!(
+        "rerun_{}",
+        path.file_name().unwrap_or_default().to_string_lossy()
+      ))
+      .to_string_lossy()
+      .to_string();
+    if let Err(e) = self.file_conversion(
+      failures_file,
+      &rerun_output,
+      &rerun_log,
+      Some(&still_failing),
+      assets.as_deref(),
+    ) {
+      let _ = remove_file(&still_failing);
+      return Err(e);
+    }
+    let merged_output = format!("{}.merged", output_file);
+    let merged_log = format!("{}.merged", log_file);
+    let merged = self.merge_rerun(
+      (output_file, log_file),
+      (&rerun_output, &rerun_log),
+      (&merged_output, &merged_log),
+    );
+    remove_file(&rerun_output)?;
+    remove_file(&rerun_log)?;
+    match merged {
+      Ok(fixed) => {
+        info!(failures_file, fixed, "merged re-run failures");
+        rename(&merged_output, output_file)?;
+        rename(&merged_log, log_file)?;
+        rename(&still_failing, failures_file)?;
+        Ok(())
+      },
+      Err(e) => {
+        // the original run is left untouched
+        let _ = remove_file(&merged_output);
+        let _ = remove_file(&merged_log);
+        let _ = remove_file(&still_failing);
+        Err(e)
+      },
+    }
+  }
+
+  /// Same as `rerun_failures`, for a directory of failures files written by `convert_dir`
+  pub fn rerun_failures_dir(
+    &self,
+    failures_dir: &str,
+    output_dir: &str,
+    log_dir: &str,
+  ) -> Result<(), Box<dyn Error>> {
+    for dir_entry in read_dir(failures_dir)?.flatten() {
+      let filename = dir_entry.file_name();
+      let entry = filename.to_string_lossy();
+      if compression::strip_extension(&entry).ends_with(".csv") {
+        self.rerun_failures(
+          &format!("{}/{}", failures_dir, entry),
+          &format!("{}/result_{}", output_dir, entry),
+          &format!("{}/{}.log", log_dir, entry),
+        )?;
+      }
+    }
     Ok(())
   }
 
+  /// Writes the original rows, with failed ones replaced by their re-run,
+  /// returning the number of re-run rows that succeeded
+  fn merge_rerun(
+    &self,
+    (output_file, log_file): (&str, &str),
+    (rerun_output, rerun_log): (&str, &str),
+    (merged_output, merged_log): (&str, &str),
+  ) -> Result<usize, Box<dyn Error>> {
+    let csv_reader = |path: &str| -> io::Result<_> {
+      Ok(
+        ReaderBuilder::new()
+          .has_headers(false)
+          .flexible(true)
+          .from_reader(compression::open(path)?),
+      )
+    };
+    let output_reader = |path: &str| -> io::Result<_> {
+      Ok(
+        self
+          .csv
+          .output
+          .reader_builder()
+          .has_headers(false)
+          .flexible(true)
+          .from_reader(compression::open(path)?),
+      )
+    };
+    // the merged files replace the original ones, and are compressed as those
+    let merged_writer =
+      |merged: &str, original: &str| Compression::from_path(original).writer(File::create(merged)?);
+    let mut original_outputs = output_reader(output_file)?.into_records();
+    let mut rerun_outputs = output_reader(rerun_output)?.into_records();
+    let mut out_writer = self
+      .csv
+      .output
+      .writer_builder()
+      .flexible(true)
+      .from_writer(merged_writer(merged_output, output_file)?);
+    if self.csv.output_headers {
+      // the header rows of both runs have no status in their logs
+      if let Some(headers) = original_outputs.next() {
+        out_writer.write_record(&headers?)?;
+      }
+      rerun_outputs.next();
+    }
+    let mut original_rows = original_outputs.zip(csv_reader(log_file)?.into_records());
+    let mut rerun_rows = rerun_outputs.zip(csv_reader(rerun_log)?.into_records());
+    let mut log_writer = WriterBuilder::new().from_writer(merged_writer(merged_log, log_file)?);
+    let mut fixed = 0;
+    for (output_row, log_row) in original_rows.by_ref() {
+      let (output_row, log_row) = (output_row?, log_row?);
+      let status_code: u8 = log_row.get(0).unwrap_or_default().trim().parse()?;
+      if self.is_rerunnable(status_code) {
+        let (rerun_output_row, rerun_log_row) = rerun_rows
+          .next()
+          .ok_or("the failures file has fewer jobs than the failed rows of the original run")?;
+        let rerun_log_row = rerun_log_row?;
+        let rerun_status_code: u8 = rerun_log_row.get(0).unwrap_or_default().trim().parse()?;
+        if rerun_status_code <= self.failure_threshold {
+          fixed += 1;
+        }
+        out_writer.write_record(&rerun_output_row?)?;
+        log_writer.write_record(&rerun_log_row)?;
+      } else {
+        out_writer.write_record(&output_row)?;
+        log_writer.write_record(&log_row)?;
+      }
+    }
+    if rerun_rows.next().is_some() {
+      return Err(
+        "the failures file has more jobs than the failed rows of the original run".into(),
+      );
+    }
+    finish(out_writer)?;
+    finish(log_writer)?;
+    Ok(fixed)
+  }
+
+  /// Converts the rows of a batch which could be read from the input, with `job` giving
+  /// the number and TeX of each row's job, given its index in the batch and its contents.
+  /// The rows which couldn't be read get the response of `unreadable`, in their place
+  fn convert_rows<'a, T, E, J, U>(
+    &self,
+    rows: &'a [Result<T, E>],
+    job: J,
+    unreadable: U,
+    assets: Option<&Path>,
+  ) -> Result<Vec<LatexmlResponse>, Box<dyn Error>>
+  where
+    T: Sync,
+    E: Sync,
+    J: Fn(usize, &'a T) -> (usize, &'a str) + Send,
+    U: Fn(&E) -> LatexmlResponse,
+  {
+    let readable = rows.iter().filter(|row| row.is_ok()).count();
+    let converted = self.convert_literals(
+      rows
+        .iter()
+        .enumerate()
+        .filter_map(move |(index, row)| row.as_ref().ok().map(|contents| job(index, contents))),
+      assets,
+    )?;
+    // We must always ensure we match inputs with outputs, or large streams become corrupted
+    let r_len = converted.len();
+    assert_eq!(
+      r_len, readable,
+      "panic: we got {} results for {} inputs!",
+      r_len, readable
+    );
+    let mut converted = converted.into_iter();
+    Ok(
+      rows
+        .iter()
+        .map(|row| match row {
+          Ok(_) => converted.next().unwrap_or_default(),
+          Err(e) => unreadable(e),
+        })
+        .collect(),
+    )
+  }
+
+  /// Whether a job with `status_code` failed, and is written to the failures file for a re-run.
+  /// Jobs which couldn't be read from their input are left out, as they can't be converted
+  fn is_rerunnable(&self, status_code: u8) -> bool {
+    status_code > self.failure_threshold
+      && !FailureKind::from_status_code(status_code).is_some_and(FailureKind::in_input)
+  }
+
   /// Convert all jobs *from* a blocking serial iterator,
   /// bridging to parallel latexmls servers via rayon.
   /// Output is returned in the same order as the input entries.
-  /// Note that you may need to batch your data before using this method,
-  /// as all output values are held in memory at the moment
-  fn convert_iterator<'a, I>(&mut self, vals: I) -> Vec<LatexmlResponse>
+  /// `first_job` is the (1-based) number of the first entry in the overall input, used in log
+  /// events and quarantine records. Note that you may need to batch your data before using
+  /// this method, as all output values are held in memory at the moment.
+  /// Failed jobs are retried as per the `retry_policy`, and if all attempts fail, their response
+  /// records the kind of failure in its status code.
+  /// Servers that fail to reboot are retired from the pool, and the conversion only fails
+  /// once no healthy servers remain.
+  pub fn convert_iterator<'a, I>(
+    &self,
+    vals: I,
+    first_job: usize,
+  ) -> Result<Vec<LatexmlResponse>, Box<dyn Error>>
   where
     I: Iterator<Item = &'a str> + Send,
   {
-    let mut results: Vec<_> = vals
+    self.convert_numbered(
+      vals
+        .enumerate()
+        .map(|(index, record)| (first_job + index, record)),
+    )
+  }
+
+  /// Same as `convert_iterator`, for jobs paired with their own number,
+  /// e.g. the input line at which a multi-line CSV or TXT record starts
+  pub fn convert_numbered<'a, I>(&self, jobs: I) -> Result<Vec<LatexmlResponse>, Box<dyn Error>>
+  where
+    I: Iterator<Item = (usize, &'a str)> + Send,
+  {
+    self.convert_literals(jobs, self.assets_dir.as_deref().map(Path::new))
+  }
+
+  /// Same as `convert_numbered`, with each job converting into its own directory under
+  /// `assets`, if any, collecting the files it generates there
+  fn convert_literals<'a, I>(
+    &self,
+    jobs: I,
+    assets: Option<&Path>,
+  ) -> Result<Vec<LatexmlResponse>, Box<dyn Error>>
+  where
+    I: Iterator<Item = (usize, &'a str)> + Send,
+  {
+    let assets = match assets {
+      Some(assets) if self.generates_assets => assets,
+      // jobs which can't generate files don't need directories of their own
+      _ => {
+        return self.convert_sources(jobs.map(|(job, record)| (job, Source::Literal(record), None)))
+      },
+    };
+    let jobs: Vec<(usize, &str)> = jobs.collect();
+    let destinations = jobs
+      .iter()
+      .map(|(job, _)| assets::prepare(assets, *job, &self.document_extension))
+      .collect::<Result<Vec<_>, _>>()?;
+    let mut results = self.convert_sources(jobs.iter().zip(destinations.iter()).map(
+      |((job, record), destination)| (*job, Source::Literal(record), Some(destination.as_path())),
+    ))?;
+    let root = self.assets_dir.as_deref().map_or(assets, Path::new);
+    for (response, destination) in results.iter_mut().zip(destinations.iter()) {
+      response.assets = assets::collect(destination, root)?;
+    }
+    Ok(results)
+  }
+
+  /// Same as `convert_numbered`, for literal jobs as well as documents,
+  /// each with the destination latexml converts into, if any
+  fn convert_sources<'a, I>(&self, jobs: I) -> Result<Vec<LatexmlResponse>, Box<dyn Error>>
+  where
+    I: Iterator<Item = (usize, Source<'a>, Option<&'a Path>)> + Send,
+  {
+    let metrics = metrics::global();
+    let mut results = jobs
       .enumerate()
+      .inspect(|_| metrics.enqueue(1))
       .par_bridge()
-      .map(|(index, record)| {
-        let mut server = self.servers.pop().unwrap();
-        let mut result = server.convert(record);
-        if result.is_err() {
-          // retry 1
-          result = server.convert(record);
-        }
-        if result.is_err() {
-          // retry 2
-          result = server.convert(record);
-        }
-        let response = match result {
-          Ok(r) => r,
-          Err(_) => LatexmlResponse::default(),
-        };
-        self.servers.push(server).unwrap();
-        (index, response)
+      .map(|(index, (job, source, destination))| {
+        Ok((index, self.convert_queued(job, source, destination)?))
       })
-      .collect();
+      .collect::<Result<Vec<_>, PoolError>>()?;
     results.sort_by_key(|x| x.0);
-    results.into_iter().map(|x| x.1).collect()
+    Ok(results.into_iter().map(|x| x.1).collect())
+  }
+
+  /// Converts a single job on the calling thread, as per the `retry_policy` and the
+  /// `quarantine`, waiting for an available server (up to `checkout_timeout`).
+  /// Safe to call from many threads at once, which then share the server pool,
+  /// rather than waiting for each other's jobs to finish
+  pub fn convert_job(&self, job: usize, tex: &str) -> Result<LatexmlResponse, PoolError> {
+    metrics::global().enqueue(1);
+    self.convert_queued(job, Source::Literal(tex), None)
   }
 
-  pub fn convert_one(&mut self, job: &str) -> Result<String, Box<dyn Error>> {
-    // select an available server
-    let mut server = self.servers.pop().unwrap();
+  /// Converts an enqueued job, recording it in the metrics
+  fn convert_queued(
+    &self,
+    job: usize,
+    source: Source<'_>,
+    destination: Option<&Path>,
+  ) -> Result<LatexmlResponse, PoolError> {
+    let metrics = metrics::global();
+    let start = Instant::now();
+    let converted = self.convert_with_retries(job, source, destination);
+    metrics.dequeue();
+    let response = converted?;
+    metrics.observe_conversion(response.status_code, start.elapsed());
+    Ok(response)
+  }
+
+  /// Applies the per-conversion limits of the harness to a checked out server
+  pub(crate) fn configure(&self, server: &mut Server) {
+    server.set_job_timeout(self.job_timeout);
+    server.set_recycle_limits(self.max_server_memory, self.max_server_age);
+    server.set_warm_standby(self.warm_standby);
+  }
+
+  /// Converts a single job as per the `retry_policy`, and the `quarantine` if any,
+  /// only failing if the server pool has been exhausted
+  fn convert_with_retries(
+    &self,
+    job: usize,
+    source: Source<'_>,
+    destination: Option<&Path>,
+  ) -> Result<LatexmlResponse, PoolError> {
+    let policy = &self.retry_policy;
+    let record = source.text();
+    if let Some(ref quarantine) = self.quarantine {
+      if quarantine.contains(&record) {
+        info!(job, "skipping quarantined input");
+        return Ok(LatexmlResponse::failure(
+          FailureKind::Quarantined,
+          &"input was already quarantined",
+        ));
+      }
+    }
+    let mut server = match self.servers.checkout(self.checkout_timeout) {
+      Ok(server) => server,
+      Err(PoolError::Timeout) => {
+        warn!(job, error = %PoolError::Timeout, "conversion failed");
+        return Ok(LatexmlResponse::failure(
+          FailureKind::Unavailable,
+          &PoolError::Timeout,
+        ));
+      },
+      Err(e) => return Err(e),
+    };
+    let mut attempt = 1;
+    // failed attempts which may have been caused by the job itself
+    let mut server_failures = 0;
+    self.configure(&mut server);
+    let mut result = server.convert_source(source, destination);
+    while let Err(ref e) = result {
+      let kind = FailureKind::classify(e.as_ref());
+      if kind.during_conversion() {
+        server_failures += 1;
+      }
+      if self.is_poison(server_failures) || !policy.should_retry(kind, attempt) {
+        break;
+      }
+      warn!(
+        job,
+        server = server.id(),
+        port = server.port(),
+        attempt,
+        failure = %kind,
+        error = ?e,
+        "retrying conversion"
+      );
+      metrics::global().inc_retries();
+      thread::sleep(policy.delay(attempt));
+      if policy.switch_servers {
+        // check this server back in, before waiting for the next available one
+        drop(server);
+        server = match self.servers.checkout(self.checkout_timeout) {
+          Ok(server) => server,
+          Err(PoolError::Timeout) => {
+            warn!(job, error = %PoolError::Timeout, "conversion failed");
+            return Ok(LatexmlResponse::failure(
+              FailureKind::Unavailable,
+              &PoolError::Timeout,
+            ));
+          },
+          Err(e) => return Err(e),
+        };
+      }
+      attempt += 1;
+      self.configure(&mut server);
+      result = server.convert_source(source, destination);
+    }
+    // the server (if still healthy) is made available again when dropped
+    match result {
+      Ok(response) => Ok(response),
+      Err(e) => {
+        let kind = FailureKind::classify(e.as_ref());
+        warn!(
+          job,
+          server = server.id(),
+          port = server.port(),
+          attempt,
+          failure = %kind,
+          error = ?e,
+          "conversion failed"
+        );
+        // the pool shrinks, rather than handing out a server that can't be rebooted
+        if let Err(boot_error) = server.ensure_server() {
+          error!(
+            job,
+            server = server.id(),
+            port = server.port(),
+            error = ?boot_error,
+            healthy = self.servers.healthy() - 1,
+            "retiring server which failed to reboot"
+          );
+          server.retire();
+        }
+        if self.is_poison(server_failures) {
+          let reason = format!(
+            "took down a server {} times, last failure was {}",
+            server_failures, e
+          );
+          warn!(job, reason = %reason, "quarantining input");
+          if let Some(ref quarantine) = self.quarantine {
+            if let Err(write_error) = quarantine.add(job, &reason, &record) {
+              error!(job, error = ?write_error, "failed to record quarantined input");
+            }
+          }
+          return Ok(LatexmlResponse::failure(FailureKind::Quarantined, &reason));
+        }
+        Ok(LatexmlResponse::failure(kind, &e))
+      },
+    }
+  }
+
+  fn is_poison(&self, server_failures: usize) -> bool {
+    match self.quarantine {
+      Some(ref quarantine) => server_failures >= quarantine.threshold,
+      None => false,
+    }
+  }
+
+  /// Converts a single job, waiting for an available server (up to `checkout_timeout`).
+  /// Safe to call from many threads at once, e.g. with the `Harness` wrapped in an `Arc`
+  pub fn convert_one(&self, job: &str) -> Result<String, Box<dyn Error>> {
+    self.convert_one_within(job, self.checkout_timeout)
+  }
+
+  /// Same as `convert_one`, but waits at most `timeout` for an available server
+  pub fn convert_one_timeout(
+    &self,
+    job: &str,
+    timeout: Duration,
+  ) -> Result<String, Box<dyn Error>> {
+    self.convert_one_within(job, Some(timeout))
+  }
+
+  fn convert_one_within(
+    &self,
+    job: &str,
+    timeout: Option<Duration>,
+  ) -> Result<String, Box<dyn Error>> {
+    // select an available server, which is made available again when dropped
+    let mut server = self.servers.checkout(timeout)?;
     // convert
+    let start = Instant::now();
+    self.configure(&mut server);
     let payload = server.convert(job)?;
-    // make server available again
-    self
-      .servers
-      .push(server)
-      .map_err(|_e| "failed to recycle server")?;
-
+    metrics::global().observe_conversion(payload.status_code, start.elapsed());
     Ok(payload.result)
   }
 }
 
 impl Drop for Harness {
   fn drop(&mut self) {
-    while let Some(server) = self.servers.pop() {
+    while let Some(server) = self.servers.try_checkout() {
       drop(server);
     }
   }
 }
+
+fn is_txt(input_file: &str) -> bool {
+  Path::new(compression::strip_extension(input_file))
+    .extension()
+    .and_then(|ext| ext.to_str())
+    == Some("txt")
+}
+
+/// Creates (or truncates) a file, along with its parent directories,
+/// compressed as per its extension
+fn create_file(path: &str) -> Result<Encoder, Box<dyn Error>> {
+  if let Some(dir) = Path::new(path).parent() {
+    create_dir_all(dir)?;
+  }
+  Ok(compression::create(path)?)
+}
+
+/// The header row of outputs, with an `assets` column when collecting assets
+fn output_headers(mut headers: Vec<String>, assets: Option<&Path>) -> Vec<String> {
+  if assets.is_some() {
+    headers.push(String::from("assets"));
+  }
+  headers
+}
+
+/// The `;`-separated files a job generated, when collecting assets
+fn listed_assets(response: &LatexmlResponse, assets: Option<&Path>) -> Option<String> {
+  assets.map(|_| response.assets.join(";"))
+}
+
+/// An output row, followed by the listed assets of its job, if any
+fn with_assets<'r>(mut row: Vec<&'r str>, listed: Option<&'r str>) -> Vec<&'r str> {
+  row.extend(listed);
+  row
+}
+
+/// Flushes a CSV writer, and finishes its compressed stream
+fn finish(writer: Writer<Encoder>) -> Result<(), Box<dyn Error>> {
+  writer.into_inner().map_err(|e| e.into_error())?.finish()?;
+  Ok(())
+}
+
+/// The placeholder response of a CSV row which couldn't be parsed,
+/// reporting where the row is in the input
+fn malformed_row(error: &csv::Error) -> LatexmlResponse {
+  let (position, reason) = match error.kind() {
+    csv::ErrorKind::UnequalLengths {
+      pos,
+      expected_len,
+      len,
+    } => (
+      pos.as_ref(),
+      format!(
+        "found {} fields, while previous rows have {}",
+        len, expected_len
+      ),
+    ),
+    csv::ErrorKind::Utf8 { pos, err } => (
+      pos.as_ref(),
+      format!("invalid UTF-8 in field {}", err.field() + 1),
+    ),
+    _ => (error.position(), error.to_string()),
+  };
+  let message = match position {
+    Some(position) => format!(
+      "malformed CSV row at line {}, byte {}: {}",
+      position.line(),
+      position.byte(),
+      reason
+    ),
+    None => format!("malformed CSV row: {}", reason),
+  };
+  warn!(error = %message, "skipping malformed input row");
+  LatexmlResponse::failure(FailureKind::MalformedInput, &message)
+}
+
+/// The placeholder response of a CSV row without a TeX column
+fn short_row(line: usize, len: usize, tex_column: usize) -> LatexmlResponse {
+  let message = format!(
+    "malformed CSV row at line {}: found {} fields, without the TeX column {}",
+    line, len, tex_column
+  );
+  warn!(error = %message, "skipping malformed input row");
+  LatexmlResponse::failure(FailureKind::MalformedInput, &message)
+}
+
+/// The placeholder response of a TXT line which couldn't be decoded
+fn malformed_line(error: &MalformedLine) -> LatexmlResponse {
+  warn!(error = %error, "skipping malformed input row");
+  LatexmlResponse::failure(FailureKind::MalformedInput, error)
+}
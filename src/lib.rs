@@ -1,3 +1,22 @@
+pub mod assets;
+#[cfg(feature = "async")]
+pub mod async_harness;
+pub mod boot;
+pub mod compression;
+pub mod config;
+pub mod csv_format;
+pub mod documents;
+pub mod failure;
 pub mod harness;
+pub mod memory;
+pub mod metrics;
+pub mod pool;
+pub mod ports;
+pub mod quarantine;
+pub mod retry;
 pub mod server;
+pub mod service;
+pub mod txt_format;
+#[cfg(feature = "async")]
+pub use async_harness::AsyncHarness;
 pub use harness::Harness;
@@ -1,23 +1,167 @@
+#![recursion_limit = "256"]
 #[macro_use]
 extern crate clap;
 extern crate csv;
 extern crate which;
 
+use std::env;
 use std::error::Error;
+use std::ffi::OsString;
+use std::path::Path;
 use std::result::Result;
+use std::time::Duration;
 
-use latexml_runner::Harness;
-use std::collections::HashSet;
+use clap::ArgMatches;
+use latexml_runner::csv_format::{self, CsvDialect, CsvOptions};
+use latexml_runner::failure::FailureKind;
+use latexml_runner::quarantine::Quarantine;
+use latexml_runner::retry::RetryPolicy;
+use latexml_runner::service::{self, ServiceOptions};
+use latexml_runner::txt_format::{self, Framing, TxtOptions};
+use latexml_runner::{config, metrics, Harness};
+use serde_json::Value;
+use tracing::level_filters::LevelFilter;
+use tracing::{info, warn};
 
-fn main() -> Result<(), Box<dyn Error>> {
-  let mut matches = clap_app!(latexml_runner =>
+fn main() {
+  // errors are reported for humans, rather than in their Debug form
+  if let Err(e) = run() {
+    eprintln!("Error: {}", e);
+    std::process::exit(1);
+  }
+}
+
+/// Arguments configuring the runner itself, rather than forwarded to latexmls,
+/// by their clap name and command-line flag
+const RUNNER_ARGS: &[(&str, &str)] = &[
+  ("CONFIG", "config"),
+  ("PRINT_CONFIG", "print-config"),
+  ("PORT", "from_port"),
+  ("INPUT", "input_file"),
+  ("OUTPUT", "output_file"),
+  ("LOG", "log_file"),
+  ("METRICS", "metrics_address"),
+  ("MAX_ATTEMPTS", "max_attempts"),
+  ("RETRY_BACKOFF", "retry_backoff"),
+  ("RETRY_ON", "retry_on"),
+  ("RETRY_SWITCH", "retry_other_server"),
+  ("QUARANTINE", "quarantine_file"),
+  ("POISON_THRESHOLD", "poison_threshold"),
+  ("FAILURES", "failures_file"),
+  ("FAILURE_THRESHOLD", "failure_threshold"),
+  ("RERUN_FAILURES", "rerun_failures"),
+  ("DOCUMENTS", "documents"),
+  ("ASSETS", "assets_dir"),
+  ("JOB_TIMEOUT", "job_timeout"),
+  ("MAX_MEMORY", "max_server_memory"),
+  ("MAX_AGE", "max_server_age"),
+  ("WARM_STANDBY", "warm_standby"),
+  ("CSV_HEADER", "csv_header"),
+  ("TEX_COLUMN", "tex_column"),
+  ("PASSTHROUGH", "passthrough_columns"),
+  ("CSV_DELIMITER", "csv_delimiter"),
+  ("CSV_QUOTE", "csv_quote"),
+  ("CSV_QUOTING", "csv_quoting"),
+  ("CSV_ESCAPE", "csv_escape"),
+  ("CSV_TERMINATOR", "csv_terminator"),
+  ("OUTPUT_DELIMITER", "output_delimiter"),
+  ("OUTPUT_QUOTE", "output_quote"),
+  ("OUTPUT_QUOTING", "output_quoting"),
+  ("OUTPUT_ESCAPE", "output_escape"),
+  ("OUTPUT_TERMINATOR", "output_terminator"),
+  ("OUTPUT_HEADER", "output_header"),
+  ("INPUT_ENCODING", "input_encoding"),
+  ("LOSSY_INPUT", "lossy_input"),
+  ("TXT_FRAMING", "txt_framing"),
+  ("TXT_SEPARATOR", "txt_separator"),
+  // forwarded to latexmls, but also used by the runner
+  ("autoflush", "autoflush"),
+  ("strict", "strict"),
+];
+
+/// The clap name of the argument given as `--flag`
+fn arg_name(flag: &str) -> &str {
+  RUNNER_ARGS
+    .iter()
+    .find(|(_, runner_flag)| *runner_flag == flag)
+    .map_or(flag, |(name, _)| name)
+}
+/// The command-line flag of the argument named `name` by clap
+fn arg_flag(name: &str) -> &str {
+  RUNNER_ARGS
+    .iter()
+    .find(|(runner_name, _)| *runner_name == name)
+    .map_or(name, |(_, flag)| flag)
+}
+
+/// The CSV dialect given by the arguments named with `prefix`, e.g. `CSV_DELIMITER`
+fn csv_dialect(matches: &ArgMatches, prefix: &str) -> Result<CsvDialect, Box<dyn Error>> {
+  let value_of = |name: &str| matches.value_of(format!("{}_{}", prefix, name));
+  let mut dialect = CsvDialect::default();
+  if let Some(delimiter) = value_of("DELIMITER") {
+    dialect.delimiter = csv_format::parse_char(delimiter)?;
+  }
+  if let Some(quote) = value_of("QUOTE") {
+    dialect.quote = csv_format::parse_char(quote)?;
+  }
+  if let Some(quoting) = value_of("QUOTING") {
+    dialect.quoting = quoting.parse()?;
+  }
+  if let Some(escape) = value_of("ESCAPE") {
+    dialect.escape = Some(csv_format::parse_char(escape)?);
+  }
+  if let Some(terminator) = value_of("TERMINATOR") {
+    dialect.terminator = Some(csv_format::parse_terminator(terminator)?);
+  }
+  Ok(dialect)
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+  let app = clap_app!(latexml_runner =>
         (version: "1.0")
         (author: "Deyan Ginev. <deyan.ginev@gmail.com>")
         (about: "A high-performance client for the latexmls daemonized socket server for LaTeXML")
+        (@setting SubcommandsNegateReqs)
+        (@arg CONFIG: -c --config +takes_value "A TOML (or YAML, by extension) file of flag names to values, for both runner and latexml options. Flags given on the command line take precedence.")
+        (@arg PRINT_CONFIG: --("print-config") "Prints the effective configuration, merging the config file and the command line, and exits")
         (@arg PORT: -p --from_port +takes_value "Sets the first port at which to deploy latexmls. Default is 3334.")
-        (@arg INPUT: -i --input_file +takes_value +required "An input CSV file containing one formula per line. OR a directory of such CSV files.")
-        (@arg OUTPUT: -o --output_file +takes_value +required "The output CSV file, containing one output formula per line, preserving input order. OR a directory for such CSV files.")
+        (@arg INPUT: -i --input_file +takes_value "Required. An input CSV file containing one formula per line. OR a directory of .tex documents, with --documents.")
+        (@arg OUTPUT: -o --output_file +takes_value "Required. The output CSV file, containing one output formula per line, preserving input order. OR a directory for the converted documents, with --documents.")
         (@arg LOG: -l --log_file +takes_value "An optional log file, containing one latexml conversion status per line, preserving input order")
+        (@arg MAX_ATTEMPTS: --max_attempts +takes_value "Maximum conversion attempts per job, including the first one. Default is 3.")
+        (@arg RETRY_BACKOFF: --retry_backoff +takes_value "Milliseconds to wait before retrying a failed job, doubling with each further retry. Default is 0.")
+        (@arg RETRY_ON: --retry_on +takes_value "Comma-separated failure kinds worth retrying, of: timeout, connection_refused, connection_lost, empty_response, malformed_response, unavailable, other. Default is all.")
+        (@arg RETRY_SWITCH: --retry_other_server "Retry failed jobs with the next available server, rather than the one that failed")
+        (@arg QUARANTINE: --quarantine_file +takes_value "An optional CSV file recording poison jobs, which repeatedly take down servers, and are no longer retried")
+        (@arg POISON_THRESHOLD: --poison_threshold +takes_value "Number of server failures after which a job is quarantined. Default is 2, if --quarantine_file is set.")
+        (@arg JOB_TIMEOUT: --job_timeout +takes_value "Seconds after which the runner abandons a conversion, and restarts its server, independently of the latexmls --timeout")
+        (@arg MAX_MEMORY: --max_server_memory +takes_value "Megabytes of resident memory, of a latexmls process and its children, after which the server is restarted")
+        (@arg MAX_AGE: --max_server_age +takes_value "Seconds after which a latexmls server is restarted")
+        (@arg WARM_STANDBY: --warm_standby "Boot the replacement of a server in the background, before it reaches --autoflush or its other restart limits")
+        (@arg CSV_HEADER: --csv_header "The first row of CSV inputs names their columns")
+        (@arg TEX_COLUMN: --tex_column +takes_value "The CSV column holding the TeX of each job, by name or 0-based index. Default is the first column.")
+        (@arg PASSTHROUGH: --passthrough_columns +takes_value "Comma-separated CSV columns, by name or 0-based index, copied to the output rows before the result")
+        (@arg CSV_DELIMITER: --csv_delimiter +takes_value "The field delimiter of CSV inputs, e.g. \\t or tab for TSV. Default is a comma.")
+        (@arg CSV_QUOTE: --csv_quote +takes_value "The quote character of CSV inputs. Default is a double quote.")
+        (@arg CSV_QUOTING: --csv_quoting +takes_value "Whether fields of CSV inputs are quoted: necessary (default) or never")
+        (@arg CSV_ESCAPE: --csv_escape +takes_value "The character escaping quotes in CSV inputs. Default is doubling quotes.")
+        (@arg CSV_TERMINATOR: --csv_terminator +takes_value "The record terminator of CSV inputs, crlf or a single character. Default is any line ending.")
+        (@arg OUTPUT_DELIMITER: --output_delimiter +takes_value "The field delimiter of CSV outputs. Default is a comma.")
+        (@arg OUTPUT_QUOTE: --output_quote +takes_value "The quote character of CSV outputs. Default is a double quote.")
+        (@arg OUTPUT_QUOTING: --output_quoting +takes_value "Which fields of CSV outputs are quoted: necessary (default), always, non_numeric or never")
+        (@arg OUTPUT_ESCAPE: --output_escape +takes_value "The character escaping quotes in CSV outputs. Default is doubling quotes.")
+        (@arg OUTPUT_TERMINATOR: --output_terminator +takes_value "The record terminator of CSV outputs, crlf or a single character. Default is \\n.")
+        (@arg OUTPUT_HEADER: --output_header "Start CSV outputs with a row naming their columns")
+        (@arg INPUT_ENCODING: --input_encoding +takes_value "The encoding of TXT inputs, e.g. latin1 or windows-1252. Default is utf-8.")
+        (@arg LOSSY_INPUT: --lossy_input "Decode invalid bytes of TXT inputs as U+FFFD, rather than failing their lines as malformed_input")
+        (@arg TXT_FRAMING: --txt_framing +takes_value "How the records of TXT inputs are separated: line (default), blank_line or nul")
+        (@arg TXT_SEPARATOR: --txt_separator +takes_value conflicts_with[TXT_FRAMING] "A line separating the multi-line records of TXT inputs")
+        (@arg FAILURES: --failures_file +takes_value "An optional file collecting the failed jobs, in the format of the input, for re-running them with --rerun_failures. OR a directory for such files.")
+        (@arg FAILURE_THRESHOLD: --failure_threshold +takes_value "Jobs with a status code above this threshold count as failed. Default is 2.")
+        (@arg RERUN_FAILURES: --rerun_failures "Converts the failures file given as input, and merges the results in place into the output and log files of its original run")
+        (@arg ASSETS: --assets_dir +takes_value "A directory where each job converts in a directory of its own, collecting the files latexml generates, e.g. with --mathimages, --svg or --split, which are listed in an extra assets column of the output")
+        (@arg DOCUMENTS: --documents conflicts_with[RERUN_FAILURES FAILURES] "Converts the .tex documents of the input directory, and its subdirectories, into a mirrored output directory. The log file records the status of each document.")
+        (@arg METRICS: --metrics_address +takes_value "An optional address (e.g. 127.0.0.1:9184) at which to export Prometheus metrics of the run over HTTP")
         (@arg pmml: --pmml "converts math to Presentation MathML (default for xhtml & html5 formats)")
         (@arg nopmml: --nopmml "disable presentation MathML output")
         (@arg cmml: --cmml "enable content MathML output")
@@ -35,8 +179,8 @@ fn main() -> Result<(), Box<dyn Error>> {
         (@arg address: --address +takes_value    "Specify server address (default: localhost)")
         (@arg port: --port +takes_value          "Specify server port (default: 3354)")
         (@arg documentid: --documentid +takes_value    "assign an id to the document root.")
-        (@arg quiet: --quiet                     "suppress messages (can repeat)")
-        (@arg verbose: --verbose                 "more informative output (can repeat)")
+        (@arg quiet: --quiet ...                 "suppress messages (can repeat), of both the runner and latexmls")
+        (@arg verbose: --verbose ...             "more informative output (can repeat), of both the runner and latexmls")
         (@arg strict: --strict                   "makes latexml less forgiving of errors")
         (@arg bibtex: --bibtex                   "processes a BibTeX bibliography.")
         (@arg xml: --xml                         "requests xml output (default).")
@@ -105,7 +249,75 @@ fn main() -> Result<(), Box<dyn Error>> {
         (@arg nocomments: --nocomments           "omit comments from the output")
         (@arg inputencoding: --inputencoding +takes_value  "specify the input encoding.")
         (@arg debug: --debug +takes_value        "enables debugging output for the named package")
-     ).get_matches();
+        (@subcommand serve =>
+          (about: "Serves conversions over a local HTTP API, using the latexml options given before the subcommand")
+          (@arg LISTEN: --listen +takes_value "The address at which to serve conversions. Default is 127.0.0.1:8080.")
+          (@arg MAX_CONNECTIONS: --max_connections +takes_value "The number of HTTP requests handled concurrently. Default is 64.")
+          (@arg QUEUE_SIZE: --queue_size +takes_value "The number of requests allowed to wait for a handler, before new ones are rejected. Default is 256.")
+        )
+     );
+  let cli_matches = app.clone().get_matches();
+  let mut matches = match cli_matches.value_of("CONFIG") {
+    Some(path) => {
+      // the config file's flags go first, before any subcommand,
+      // and are only used if not also given on the command line
+      let file_arguments = config::to_arguments(&config::load(path)?, |flag| {
+        cli_matches.is_present(arg_name(flag))
+      })?;
+      let mut arguments: Vec<OsString> = env::args_os().take(1).collect();
+      arguments.extend(file_arguments.into_iter().map(OsString::from));
+      arguments.extend(env::args_os().skip(1));
+      app.get_matches_from(arguments)
+    },
+    None => cli_matches,
+  };
+  if matches.is_present("PRINT_CONFIG") {
+    let mut effective = config::Config::new();
+    // in command-line order, which is also the order options are forwarded in
+    let mut args: Vec<_> = matches.args.iter().collect();
+    args.sort_by_key(|(name, _)| matches.index_of(name));
+    for (name, arg) in args {
+      if *name == "CONFIG" || *name == "PRINT_CONFIG" {
+        continue;
+      }
+      let mut values: Vec<Value> = arg
+        .vals
+        .iter()
+        .map(|value| config::from_argument(&value.to_string_lossy()))
+        .collect();
+      if values.is_empty() {
+        // a flag stands for `true`, and a repeated one (e.g. --verbose) for one `true` each time
+        values = vec![Value::Bool(true); arg.occurs as usize];
+      }
+      let value = match values.len() {
+        1 => values.remove(0),
+        _ => Value::Array(values),
+      };
+      effective.insert(arg_flag(name).to_string(), value);
+    }
+    print!("{}", config::render(&effective)?);
+    return Ok(());
+  }
+  if matches.subcommand_matches("serve").is_none()
+    && !(matches.is_present("INPUT") && matches.is_present("OUTPUT"))
+  {
+    return Err("both an --input_file and an --output_file are required, unless serving".into());
+  }
+
+  // runner diagnostics are log events on stderr, so that stdout stays clean
+  let verbosity = matches.occurrences_of("verbose") as i64 - matches.occurrences_of("quiet") as i64;
+  let max_level = match verbosity {
+    i64::MIN..=-3 => LevelFilter::OFF,
+    -2 => LevelFilter::ERROR,
+    -1 => LevelFilter::WARN,
+    0 => LevelFilter::INFO,
+    1 => LevelFilter::DEBUG,
+    _ => LevelFilter::TRACE,
+  };
+  tracing_subscriber::fmt()
+    .with_max_level(max_level)
+    .with_writer(std::io::stderr)
+    .init();
 
   let from_port: u16 = if let Some(port_str) = matches.value_of("PORT") {
     port_str.parse().unwrap()
@@ -113,54 +325,188 @@ fn main() -> Result<(), Box<dyn Error>> {
     3334
   };
 
-  let input_file = matches.value_of("INPUT").unwrap().to_string();
-  let output_file = matches.value_of("OUTPUT").unwrap().to_string();
+  let serve_matches = matches.subcommand_matches("serve").cloned();
+  let input_file = matches.value_of("INPUT").map(|input| input.to_string());
+  let output_file = matches.value_of("OUTPUT").map(|output| output.to_string());
   let log_file = matches.value_of("LOG").unwrap_or("runner.log").to_string();
   let autoflush = matches
     .value_of("autoflush")
     .unwrap_or("0")
     .parse::<usize>()
     .unwrap_or(0);
-  matches.args.remove("PORT");
-  matches.args.remove("INPUT");
-  matches.args.remove("OUTPUT");
-  matches.args.remove("LOG");
-  matches.args.remove("autoflush");
-  let mut boot_latexmls_opts = Vec::new();
-  // clap option parsing mangles order, so we'll just impose the standard one for requested math
-  // pmml is primary, followed by cmml, mathtex,
-  let mut deferred_math = HashSet::new();
-  for key in matches.args.keys() {
-    let mut name_only = true;
-    for val in matches.values_of(key).unwrap() {
-      name_only = false;
-      boot_latexmls_opts.push((key.to_string(), val.to_string()));
-    }
-    if name_only {
-      match *key {
-        "pmml" | "cmml" | "openmath" | "mathtex" | "nopmml" | "nocmml" | "noopenmath"
-        | "nomathtex" => {
-          deferred_math.insert(*key);
-        }
-        _ => boot_latexmls_opts.push((key.to_string(), String::new())),
-      }
+  let metrics_address = matches.value_of("METRICS").map(|addr| addr.to_string());
+  let mut retry_policy = RetryPolicy::default();
+  if let Some(max_attempts) = matches.value_of("MAX_ATTEMPTS") {
+    retry_policy.max_attempts = max_attempts.parse()?;
+  }
+  if let Some(backoff) = matches.value_of("RETRY_BACKOFF") {
+    retry_policy.backoff = Duration::from_millis(backoff.parse()?);
+  }
+  if let Some(kinds) = matches.value_of("RETRY_ON") {
+    retry_policy.retry_on = kinds
+      .split(',')
+      .map(|kind| kind.trim().parse::<FailureKind>())
+      .collect::<Result<_, _>>()?;
+  }
+  retry_policy.switch_servers = matches.is_present("RETRY_SWITCH");
+  let poison_threshold = match matches.value_of("POISON_THRESHOLD") {
+    Some(threshold) => Some(threshold.parse::<usize>()?),
+    None => None,
+  };
+  let quarantine = match (matches.value_of("QUARANTINE"), poison_threshold) {
+    (Some(path), threshold) => Some(Quarantine::with_file(threshold.unwrap_or(2), path)?),
+    (None, Some(threshold)) => Some(Quarantine::new(threshold)),
+    (None, None) => None,
+  };
+  let failures_file = matches.value_of("FAILURES").map(|path| path.to_string());
+  let failure_threshold = match matches.value_of("FAILURE_THRESHOLD") {
+    Some(threshold) => Some(threshold.parse::<u8>()?),
+    None => None,
+  };
+  let rerun_failures = matches.is_present("RERUN_FAILURES");
+  let documents = matches.is_present("DOCUMENTS");
+  let assets_dir = matches.value_of("ASSETS").map(str::to_string);
+  // also forwarded to latexmls, but makes problems while booting fatal
+  let strict = matches.is_present("strict");
+  let max_server_memory = match matches.value_of("MAX_MEMORY") {
+    Some(megabytes) => Some(megabytes.parse::<u64>()? * 1024 * 1024),
+    None => None,
+  };
+  let max_server_age = match matches.value_of("MAX_AGE") {
+    Some(seconds) => Some(Duration::from_secs_f64(seconds.parse()?)),
+    None => None,
+  };
+  let warm_standby = matches.is_present("WARM_STANDBY");
+  let csv_options = CsvOptions {
+    has_headers: matches.is_present("CSV_HEADER"),
+    tex_column: match matches.value_of("TEX_COLUMN") {
+      Some(column) => Some(column.parse()?),
+      None => None,
+    },
+    passthrough_columns: match matches.value_of("PASSTHROUGH") {
+      Some(columns) => columns
+        .split(',')
+        .map(str::parse)
+        .collect::<Result<_, _>>()?,
+      None => Vec::new(),
+    },
+    input: csv_dialect(&matches, "CSV")?,
+    output: csv_dialect(&matches, "OUTPUT")?,
+    output_headers: matches.is_present("OUTPUT_HEADER"),
+  };
+  let txt_options = TxtOptions {
+    encoding: match matches.value_of("INPUT_ENCODING") {
+      Some(label) => txt_format::parse_encoding(label)?,
+      None => TxtOptions::default().encoding,
+    },
+    lossy: matches.is_present("LOSSY_INPUT"),
+    framing: match (
+      matches.value_of("TXT_SEPARATOR"),
+      matches.value_of("TXT_FRAMING"),
+    ) {
+      (Some(separator), _) => Framing::Separator(separator.to_string()),
+      (None, Some(framing)) => framing.parse()?,
+      (None, None) => Framing::Line,
+    },
+  };
+  let job_timeout = match matches.value_of("JOB_TIMEOUT") {
+    Some(seconds) => Some(Duration::from_secs_f64(seconds.parse()?)),
+    None => None,
+  };
+  for (runner_arg, _) in RUNNER_ARGS.iter() {
+    // strict is also a latexml option
+    if *runner_arg != "strict" {
+      matches.args.remove(runner_arg);
     }
   }
-  for math_key in &[
-    "pmml",
-    "cmml",
-    "openmath",
-    "mathtex",
-    "nopmml",
-    "nocmml",
-    "noopenmath",
-    "nomathtex",
-  ] {
-    if deferred_math.contains(math_key) {
-      boot_latexmls_opts.push((math_key.to_string(), String::new()))
+  // forward the latexml options in their exact command-line order, as with latexmlc,
+  // by the position of each flag occurrence, or of each of its values
+  let mut ordered_opts = Vec::new();
+  for key in matches.args.keys() {
+    let indices: Vec<usize> = matches
+      .indices_of(key)
+      .map(|indices| indices.collect())
+      .unwrap_or_default();
+    match matches.values_of(key) {
+      Some(values) if !matches.args[key].vals.is_empty() => {
+        for (index, value) in indices.into_iter().zip(values) {
+          ordered_opts.push((index, key.to_string(), value.to_string()));
+        }
+      },
+      // repeatable flags, such as --quiet and --verbose, are forwarded once per occurrence
+      _ => {
+        for index in indices {
+          ordered_opts.push((index, key.to_string(), String::new()));
+        }
+      },
     }
   }
+  ordered_opts.sort_by_key(|(index, _, _)| *index);
+  let boot_latexmls_opts: Vec<(String, String)> = ordered_opts
+    .into_iter()
+    .map(|(_, key, value)| (key, value))
+    .collect();
 
+  if let Some(address) = metrics_address {
+    metrics::serve(&address)?;
+  }
   let mut harness = Harness::new(from_port, autoflush, boot_latexmls_opts)?;
-  harness.convert_file(&input_file, &output_file, &log_file)
+  let boot_report = harness.boot_report();
+  for message in boot_report.messages() {
+    warn!(message, "latexmls reported a problem while booting");
+  }
+  info!(
+    servers = boot_report.servers.len(),
+    status_code = boot_report.status_code(),
+    "booted latexmls servers"
+  );
+  if strict && !boot_report.is_clean() {
+    return Err(
+      format!(
+        "latexmls reported problems while booting, which --strict makes fatal:\n{}",
+        boot_report.messages().join("\n")
+      )
+      .into(),
+    );
+  }
+  harness.retry_policy = retry_policy;
+  harness.quarantine = quarantine;
+  harness.failures_file = failures_file;
+  harness.job_timeout = job_timeout;
+  harness.max_server_memory = max_server_memory;
+  harness.max_server_age = max_server_age;
+  harness.warm_standby = warm_standby;
+  harness.csv = csv_options;
+  harness.txt = txt_options;
+  harness.assets_dir = assets_dir;
+  if let Some(threshold) = failure_threshold {
+    harness.failure_threshold = threshold;
+  }
+  if let Some(serve_matches) = serve_matches {
+    let defaults = ServiceOptions::default();
+    let options = ServiceOptions {
+      max_connections: match serve_matches.value_of("MAX_CONNECTIONS") {
+        Some(count) => count.parse()?,
+        None => defaults.max_connections,
+      },
+      queue_size: match serve_matches.value_of("QUEUE_SIZE") {
+        Some(size) => size.parse()?,
+        None => defaults.queue_size,
+      },
+      ..defaults
+    };
+    let address = serve_matches.value_of("LISTEN").unwrap_or("127.0.0.1:8080");
+    service::serve(harness, address, options)
+  } else if rerun_failures {
+    let (input_file, output_file) = (input_file.unwrap(), output_file.unwrap());
+    if Path::new(&input_file).is_dir() {
+      harness.rerun_failures_dir(&input_file, &output_file, &log_file)
+    } else {
+      harness.rerun_failures(&input_file, &output_file, &log_file)
+    }
+  } else if documents {
+    harness.convert_documents(&input_file.unwrap(), &output_file.unwrap(), &log_file)
+  } else {
+    harness.convert_file(&input_file.unwrap(), &output_file.unwrap(), &log_file)
+  }
 }
@@ -0,0 +1,44 @@
+//! Resident memory of latexmls processes, as reported by the Linux /proc filesystem.
+//!
+//! latexmls forks to serve its requests, so the memory of a server is that of its
+//! whole process tree, rather than of the pid the runner spawned.
+use std::collections::HashMap;
+use std::fs::{read_dir, read_to_string};
+
+/// The resident set size, in bytes, of process `pid` and all of its descendants.
+/// `None` if the process doesn't exist, or /proc is unavailable (e.g. on non-Linux hosts)
+pub fn tree_rss(pid: u32) -> Option<u64> {
+  let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+  for entry in read_dir("/proc").ok()?.flatten() {
+    if let Ok(child) = entry.file_name().to_string_lossy().parse::<u32>() {
+      if let Some(parent) = parent_of(child) {
+        children.entry(parent).or_default().push(child);
+      }
+    }
+  }
+  let mut total = rss(pid)?;
+  let mut descendants = children.get(&pid).cloned().unwrap_or_default();
+  while let Some(descendant) = descendants.pop() {
+    // processes may exit while we walk the tree
+    total += rss(descendant).unwrap_or(0);
+    if let Some(grandchildren) = children.get(&descendant) {
+      descendants.extend(grandchildren);
+    }
+  }
+  Some(total)
+}
+
+/// The resident set size of a single process, in bytes
+pub fn rss(pid: u32) -> Option<u64> {
+  let status = read_to_string(format!("/proc/{}/status", pid)).ok()?;
+  let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+  let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+  Some(kilobytes * 1024)
+}
+
+fn parent_of(pid: u32) -> Option<u32> {
+  let stat = read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+  // the command name may contain spaces and parentheses, the fields after it don't
+  let fields = &stat[stat.rfind(')')? + 1..];
+  fields.split_whitespace().nth(1)?.parse().ok()
+}
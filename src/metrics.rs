@@ -0,0 +1,207 @@
+//! Process-wide counters and histograms for long-running conversions,
+//! optionally exported in the Prometheus text format over a tiny local HTTP endpoint.
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as FmtWrite;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::result::Result;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// Upper bounds (in seconds) of the conversion latency histogram buckets
+pub const LATENCY_BUCKETS: [f64; 12] = [
+  0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 120.0,
+];
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+  conversions: Mutex<BTreeMap<u8, u64>>,
+  latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+  latency_count: AtomicU64,
+  latency_sum_micros: AtomicU64,
+  retries: AtomicU64,
+  rotations: AtomicU64,
+  active_servers: AtomicI64,
+  queue_depth: AtomicI64,
+}
+
+static GLOBAL_METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// The metrics registry shared by all harnesses and servers in this process
+pub fn global() -> &'static Metrics {
+  GLOBAL_METRICS.get_or_init(Metrics::default)
+}
+
+impl Metrics {
+  /// Records a finished conversion job, with its final status code and wall-clock latency
+  pub fn observe_conversion(&self, status_code: u8, latency: Duration) {
+    *self
+      .conversions
+      .lock()
+      .unwrap()
+      .entry(status_code)
+      .or_insert(0) += 1;
+    let seconds = latency.as_secs_f64();
+    for (bucket, bound) in self.latency_buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+      if seconds <= *bound {
+        bucket.fetch_add(1, Ordering::Relaxed);
+      }
+    }
+    self.latency_count.fetch_add(1, Ordering::Relaxed);
+    self
+      .latency_sum_micros
+      .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+  }
+  /// Records a repeated conversion attempt of a job
+  pub fn inc_retries(&self) {
+    self.retries.fetch_add(1, Ordering::Relaxed);
+  }
+  /// Records a server port rotation (e.g. triggered by `autoflush`)
+  pub fn inc_rotations(&self) {
+    self.rotations.fetch_add(1, Ordering::Relaxed);
+  }
+  /// Records a latexmls process being spawned
+  pub fn server_started(&self) {
+    self.active_servers.fetch_add(1, Ordering::Relaxed);
+  }
+  /// Records a latexmls process being terminated or reaped
+  pub fn server_stopped(&self) {
+    self.active_servers.fetch_sub(1, Ordering::Relaxed);
+  }
+  /// Adds jobs that are waiting for (or currently in) conversion
+  pub fn enqueue(&self, count: usize) {
+    self.queue_depth.fetch_add(count as i64, Ordering::Relaxed);
+  }
+  /// Removes a job from the conversion queue, once it has a final response
+  pub fn dequeue(&self) {
+    self.queue_depth.fetch_sub(1, Ordering::Relaxed);
+  }
+
+  pub fn retries(&self) -> u64 {
+    self.retries.load(Ordering::Relaxed)
+  }
+  pub fn rotations(&self) -> u64 {
+    self.rotations.load(Ordering::Relaxed)
+  }
+  pub fn active_servers(&self) -> i64 {
+    self.active_servers.load(Ordering::Relaxed)
+  }
+  pub fn queue_depth(&self) -> i64 {
+    self.queue_depth.load(Ordering::Relaxed)
+  }
+
+  /// Renders all metrics in the Prometheus text exposition format (version 0.0.4)
+  pub fn render(&self) -> String {
+    let mut out = String::new();
+    out.push_str(
+      "# HELP latexml_runner_conversions_total Finished conversion jobs, by status code.\n",
+    );
+    out.push_str("# TYPE latexml_runner_conversions_total counter\n");
+    for (code, count) in self.conversions.lock().unwrap().iter() {
+      let _ = writeln!(
+        out,
+        "latexml_runner_conversions_total{{status_code=\"{}\"}} {}",
+        code, count
+      );
+    }
+    out.push_str(
+      "# HELP latexml_runner_conversion_seconds Wall-clock latency of conversion jobs, including \
+       retries.\n",
+    );
+    out.push_str("# TYPE latexml_runner_conversion_seconds histogram\n");
+    for (bucket, bound) in self.latency_buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+      let _ = writeln!(
+        out,
+        "latexml_runner_conversion_seconds_bucket{{le=\"{}\"}} {}",
+        bound,
+        bucket.load(Ordering::Relaxed)
+      );
+    }
+    let count = self.latency_count.load(Ordering::Relaxed);
+    let _ = writeln!(
+      out,
+      "latexml_runner_conversion_seconds_bucket{{le=\"+Inf\"}} {}",
+      count
+    );
+    let _ = writeln!(
+      out,
+      "latexml_runner_conversion_seconds_sum {}",
+      self.latency_sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+    );
+    let _ = writeln!(out, "latexml_runner_conversion_seconds_count {}", count);
+    for (name, kind, help, value) in [
+      (
+        "latexml_runner_retries_total",
+        "counter",
+        "Repeated conversion attempts.",
+        self.retries() as i64,
+      ),
+      (
+        "latexml_runner_port_rotations_total",
+        "counter",
+        "Server port rotations, e.g. due to autoflush.",
+        self.rotations() as i64,
+      ),
+      (
+        "latexml_runner_active_servers",
+        "gauge",
+        "Running latexmls processes.",
+        self.active_servers(),
+      ),
+      (
+        "latexml_runner_queue_depth",
+        "gauge",
+        "Jobs of the current batch still awaiting a response.",
+        self.queue_depth(),
+      ),
+    ] {
+      let _ = writeln!(
+        out,
+        "# HELP {} {}\n# TYPE {} {}\n{} {}",
+        name, help, name, kind, name, value
+      );
+    }
+    out
+  }
+}
+
+/// Starts a background thread serving the global metrics over HTTP at `address`
+/// (e.g. "127.0.0.1:9184"), for any request path.
+pub fn serve(address: &str) -> Result<thread::JoinHandle<()>, Box<dyn Error>> {
+  let listener = TcpListener::bind(address)?;
+  let handle = thread::Builder::new()
+    .name(String::from("metrics-exporter"))
+    .spawn(move || {
+      for stream in listener.incoming().flatten() {
+        // a misbehaving scraper should never take the exporter down
+        let _ = respond(stream);
+      }
+    })?;
+  Ok(handle)
+}
+
+fn respond(stream: TcpStream) -> Result<(), Box<dyn Error>> {
+  stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+  let mut reader = BufReader::new(stream);
+  // drain the request head, we serve the same payload for every path
+  let mut line = String::new();
+  while reader.read_line(&mut line)? > 0 {
+    if line == "\r\n" || line == "\n" {
+      break;
+    }
+    line.clear();
+  }
+  let body = global().render();
+  let mut stream = reader.into_inner();
+  write!(
+    stream,
+    "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+    body.len(),
+    body
+  )?;
+  stream.flush()?;
+  Ok(())
+}
@@ -0,0 +1,217 @@
+//! A pool of latexmls servers, shared by all conversion threads of a `Harness`.
+use std::error::Error;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::result::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crossbeam::queue::ArrayQueue;
+
+use crate::server::Server;
+
+#[derive(Debug)]
+pub struct ServerPool {
+  queue: ArrayQueue<Server>,
+  // servers owned by the pool, whether idle or checked out
+  healthy: AtomicUsize,
+  // only guards waiting for a server, the queue itself is lock-free
+  lock: Mutex<()>,
+  available: Condvar,
+  // wakes up async tasks waiting for a server, which can't wait on the condition variable
+  #[cfg(feature = "async")]
+  returned: tokio::sync::Notify,
+}
+
+/// Reasons for failing to check out a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolError {
+  /// No server became available in time
+  Timeout,
+  /// All servers were retired, none will ever become available
+  Exhausted,
+}
+impl fmt::Display for PoolError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PoolError::Timeout => write!(f, "timed out waiting for an available latexmls server"),
+      PoolError::Exhausted => write!(f, "no healthy latexmls servers remain"),
+    }
+  }
+}
+impl Error for PoolError {}
+
+/// A server checked out of a pool, which is checked back in when dropped
+#[derive(Debug)]
+pub struct PooledServer<'a> {
+  server: Option<Server>,
+  pool: &'a ServerPool,
+}
+
+impl ServerPool {
+  /// Creates an empty pool, with room for `capacity` servers
+  pub fn new(capacity: usize) -> Self {
+    ServerPool {
+      queue: ArrayQueue::new(capacity),
+      healthy: AtomicUsize::new(0),
+      lock: Mutex::new(()),
+      available: Condvar::new(),
+      #[cfg(feature = "async")]
+      returned: tokio::sync::Notify::new(),
+    }
+  }
+
+  /// The number of servers currently idle in the pool
+  pub fn len(&self) -> usize {
+    self.queue.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.queue.is_empty()
+  }
+  /// The number of servers owned by the pool, idle or checked out
+  pub fn healthy(&self) -> usize {
+    self.healthy.load(Ordering::SeqCst)
+  }
+  /// The maximum number of servers the pool can hold
+  pub fn capacity(&self) -> usize {
+    self.queue.capacity()
+  }
+
+  /// Takes an idle server out of the pool, if any
+  pub fn try_checkout(&self) -> Option<Server> {
+    self.queue.pop()
+  }
+
+  /// Adds a newly booted server to the pool
+  pub fn add(&self, server: Server) -> Result<(), Box<dyn Error>> {
+    self.healthy.fetch_add(1, Ordering::SeqCst);
+    self.checkin(server).inspect_err(|_| {
+      self.healthy.fetch_sub(1, Ordering::SeqCst);
+    })
+  }
+
+  /// Takes a server out of the pool, blocking until one is available,
+  /// or until `timeout` elapses, if one is given.
+  /// Fails right away once all servers have been retired.
+  pub fn checkout(&self, timeout: Option<Duration>) -> Result<PooledServer<'_>, PoolError> {
+    let server = self.checkout_owned(timeout)?;
+    Ok(PooledServer {
+      server: Some(server),
+      pool: self,
+    })
+  }
+
+  /// Same as `checkout`, but the caller is responsible for calling `checkin` when done
+  pub fn checkout_owned(&self, timeout: Option<Duration>) -> Result<Server, PoolError> {
+    if let Some(server) = self.queue.pop() {
+      return Ok(server);
+    }
+    let deadline = timeout.map(|duration| Instant::now() + duration);
+    let mut guard = self.lock.lock().unwrap();
+    loop {
+      if let Some(server) = self.queue.pop() {
+        return Ok(server);
+      }
+      if self.healthy() == 0 {
+        return Err(PoolError::Exhausted);
+      }
+      guard = match deadline {
+        None => self.available.wait(guard).unwrap(),
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            return Err(PoolError::Timeout);
+          }
+          self
+            .available
+            .wait_timeout(guard, deadline - now)
+            .unwrap()
+            .0
+        },
+      };
+    }
+  }
+
+  /// Same as `checkout_owned`, but waits asynchronously, e.g. in a tokio task,
+  /// so that dropping the returned future stops waiting, without taking a server
+  #[cfg(feature = "async")]
+  pub async fn checkout_async(&self, timeout: Option<Duration>) -> Result<Server, PoolError> {
+    let wait = async {
+      loop {
+        let returned = self.returned.notified();
+        tokio::pin!(returned);
+        // registered before checking the queue, so that no checkin goes unnoticed
+        returned.as_mut().enable();
+        if let Some(server) = self.queue.pop() {
+          return Ok(server);
+        }
+        if self.healthy() == 0 {
+          return Err(PoolError::Exhausted);
+        }
+        returned.await;
+      }
+    };
+    match timeout {
+      Some(timeout) => tokio::time::timeout(timeout, wait)
+        .await
+        .unwrap_or(Err(PoolError::Timeout)),
+      None => wait.await,
+    }
+  }
+
+  /// Returns a server to the pool, waking up a thread waiting for one
+  pub fn checkin(&self, server: Server) -> Result<(), Box<dyn Error>> {
+    self
+      .queue
+      .push(server)
+      .map_err(|_| "failed to recycle server, pool is full")?;
+    // taking the lock ensures a waiting thread is either already asleep, or yet to check the queue
+    drop(self.lock.lock().unwrap());
+    self.available.notify_one();
+    #[cfg(feature = "async")]
+    self.returned.notify_one();
+    Ok(())
+  }
+
+  /// Permanently removes a checked out server from the pool, e.g. when it can't be rebooted,
+  /// terminating its latexmls process
+  pub fn retire(&self, server: Server) {
+    drop(server);
+    self.healthy.fetch_sub(1, Ordering::SeqCst);
+    // all waiting threads need to learn if the pool is now exhausted
+    drop(self.lock.lock().unwrap());
+    self.available.notify_all();
+    #[cfg(feature = "async")]
+    self.returned.notify_waiters();
+  }
+}
+
+impl<'a> PooledServer<'a> {
+  /// Retires this server from its pool, rather than checking it back in
+  pub fn retire(mut self) {
+    if let Some(server) = self.server.take() {
+      self.pool.retire(server);
+    }
+  }
+}
+
+impl<'a> Deref for PooledServer<'a> {
+  type Target = Server;
+  fn deref(&self) -> &Server {
+    self.server.as_ref().unwrap()
+  }
+}
+impl<'a> DerefMut for PooledServer<'a> {
+  fn deref_mut(&mut self) -> &mut Server {
+    self.server.as_mut().unwrap()
+  }
+}
+impl<'a> Drop for PooledServer<'a> {
+  fn drop(&mut self) {
+    if let Some(server) = self.server.take() {
+      // a pool never hands out more servers than it has room for
+      let _ = self.pool.checkin(server);
+    }
+  }
+}
@@ -0,0 +1,65 @@
+//! Allocation of the ports latexmls servers listen at.
+//!
+//! Ports are handed out uniquely across all servers of the process, and only if they can
+//! currently be bound, so that rotating servers never collide with each other,
+//! with another runner, or with a latexmls process which hasn't released its port yet.
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::net::TcpListener;
+use std::sync::{Mutex, OnceLock};
+
+#[derive(Debug, Default)]
+pub struct PortAllocator {
+  in_use: Mutex<HashSet<u16>>,
+}
+
+static GLOBAL_PORTS: OnceLock<PortAllocator> = OnceLock::new();
+
+/// The port allocator shared by all harnesses and servers in this process
+pub fn global() -> &'static PortAllocator {
+  GLOBAL_PORTS.get_or_init(PortAllocator::default)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortsExhausted {
+  pub from: u16,
+}
+impl fmt::Display for PortsExhausted {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "no bindable port left from port {}", self.from)
+  }
+}
+impl Error for PortsExhausted {}
+
+impl PortAllocator {
+  pub fn new() -> Self {
+    PortAllocator::default()
+  }
+
+  /// Allocates the first port from `from` onwards which is neither allocated
+  /// nor bound by any other process, until it is `release`d
+  pub fn allocate(&self, from: u16) -> Result<u16, PortsExhausted> {
+    let mut in_use = self.in_use.lock().unwrap();
+    let port = (from.max(1)..=u16::MAX)
+      .find(|port| !in_use.contains(port) && is_bindable(*port))
+      .ok_or(PortsExhausted { from })?;
+    in_use.insert(port);
+    Ok(port)
+  }
+
+  /// Makes `port` available for allocation again
+  pub fn release(&self, port: u16) {
+    self.in_use.lock().unwrap().remove(&port);
+  }
+
+  /// Whether `port` is currently allocated
+  pub fn is_allocated(&self, port: u16) -> bool {
+    self.in_use.lock().unwrap().contains(&port)
+  }
+}
+
+/// Whether a latexmls server could currently listen at `port`
+pub fn is_bindable(port: u16) -> bool {
+  TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
@@ -0,0 +1,73 @@
+//! Detection of poison jobs, i.e. inputs which repeatedly take down latexmls servers,
+//! so that they are no longer retried (or converted again, if repeated in the input).
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::result::Result;
+use std::sync::Mutex;
+
+use csv::{Writer, WriterBuilder};
+
+#[derive(Debug)]
+pub struct Quarantine {
+  /// Number of server failures during the conversion of a single job,
+  /// after which the job is considered poison
+  pub threshold: usize,
+  writer: Option<Mutex<Writer<File>>>,
+  // hashes of the quarantined inputs
+  known: Mutex<HashSet<u64>>,
+}
+
+impl Quarantine {
+  /// A quarantine which only remembers poison jobs for the lifetime of the harness
+  pub fn new(threshold: usize) -> Self {
+    Quarantine {
+      threshold: threshold.max(1),
+      writer: None,
+      known: Mutex::new(HashSet::new()),
+    }
+  }
+
+  /// A quarantine which also records poison jobs into a CSV file,
+  /// with their input line number, failure reason and TeX input
+  pub fn with_file(threshold: usize, path: &str) -> Result<Self, Box<dyn Error>> {
+    if let Some(dir) = Path::new(path).parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    let mut writer = WriterBuilder::new().from_path(path)?;
+    writer.write_record(["line", "reason", "input"])?;
+    writer.flush()?;
+    Ok(Quarantine {
+      writer: Some(Mutex::new(writer)),
+      ..Quarantine::new(threshold)
+    })
+  }
+
+  /// Whether `input` was already quarantined
+  pub fn contains(&self, input: &str) -> bool {
+    self.known.lock().unwrap().contains(&digest(input))
+  }
+
+  /// Quarantines `input`, found at `line` of the input file, for the given `reason`
+  pub fn add(&self, line: usize, reason: &str, input: &str) -> Result<(), Box<dyn Error>> {
+    // racing workers may quarantine the same input, which is only recorded once
+    if !self.known.lock().unwrap().insert(digest(input)) {
+      return Ok(());
+    }
+    if let Some(ref writer) = self.writer {
+      let mut writer = writer.lock().unwrap();
+      writer.write_record([line.to_string().as_str(), reason, input])?;
+      writer.flush()?;
+    }
+    Ok(())
+  }
+}
+
+fn digest(input: &str) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  input.hash(&mut hasher);
+  hasher.finish()
+}
@@ -0,0 +1,54 @@
+//! Configurable retrying of failed conversion jobs
+use std::time::Duration;
+
+use crate::failure::{FailureKind, ALL_FAILURE_KINDS};
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+  /// Maximum number of conversion attempts per job, including the first one
+  pub max_attempts: usize,
+  /// Delay before the first retry
+  pub backoff: Duration,
+  /// Factor by which the delay grows with each further retry
+  pub backoff_multiplier: u32,
+  /// Classes of failures worth retrying, others fail the job right away
+  pub retry_on: Vec<FailureKind>,
+  /// Whether to retry with the next available server, rather than the one that just failed
+  pub switch_servers: bool,
+}
+
+impl Default for RetryPolicy {
+  /// Three immediate attempts on the same server, for any kind of failure
+  fn default() -> Self {
+    RetryPolicy {
+      max_attempts: 3,
+      backoff: Duration::from_millis(0),
+      backoff_multiplier: 2,
+      retry_on: ALL_FAILURE_KINDS.to_vec(),
+      switch_servers: false,
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// A policy attempting each job only once
+  pub fn never() -> Self {
+    RetryPolicy {
+      max_attempts: 1,
+      ..RetryPolicy::default()
+    }
+  }
+
+  /// Whether a job that failed with `kind` on its `attempt`-th attempt should be retried
+  pub fn should_retry(&self, kind: FailureKind, attempt: usize) -> bool {
+    attempt < self.max_attempts && self.retry_on.contains(&kind)
+  }
+
+  /// How long to wait before the retry following the `attempt`-th attempt
+  pub fn delay(&self, attempt: usize) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16) as u32;
+    self
+      .backoff
+      .saturating_mul(self.backoff_multiplier.saturating_pow(exponent))
+  }
+}
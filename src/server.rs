@@ -1,18 +1,32 @@
 use rand::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::error::Error;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::net::{Shutdown, TcpStream};
+use std::path::Path;
 use std::process::{Child, Command};
 use std::result::Result;
-use std::{thread, time};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{self, Duration, Instant};
+use tracing::{error, info, trace, warn};
 use urlencoding::encode;
-#[derive(Debug, Deserialize)]
+
+use crate::failure::{ConversionError, FailureKind};
+use crate::memory;
+use crate::metrics;
+use crate::ports;
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LatexmlResponse {
   pub status_code: u8,
   pub status: String,
   pub result: String,
   pub log: String,
+  /// The files latexml generated next to the result, e.g. images with `--mathimages`,
+  /// as collected by the runner, relative to the harness `assets_dir`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub assets: Vec<String>,
 }
 impl Default for LatexmlResponse {
   fn default() -> Self {
@@ -21,34 +35,85 @@ impl Default for LatexmlResponse {
       status: String::from("Default latexml_runner fatal"),
       log: String::from("Default latexml_runner fatal"),
       result: String::new(),
+      assets: Vec::new(),
+    }
+  }
+}
+/// The source of a conversion job
+#[derive(Debug, Clone, Copy)]
+pub enum Source<'a> {
+  /// TeX given literally, e.g. a formula
+  Literal(&'a str),
+  /// A TeX document on disk, converted with its directory as base and search path
+  Document(&'a Path),
+}
+impl<'a> Source<'a> {
+  /// The TeX of a literal job, or the path of a document, e.g. for quarantine records
+  pub fn text(&self) -> Cow<'a, str> {
+    match self {
+      Source::Literal(tex) => Cow::Borrowed(tex),
+      Source::Document(path) => path.to_string_lossy(),
     }
   }
 }
+
 impl LatexmlResponse {
+  /// A response recording a job that failed without a latexmls response,
+  /// with a dedicated status code for the kind of failure
+  pub fn failure(kind: FailureKind, error: &dyn std::fmt::Display) -> Self {
+    LatexmlResponse {
+      status_code: kind.status_code(),
+      status: format!("latexml_runner fatal: {}", kind),
+      log: error.to_string(),
+      result: String::new(),
+      assets: Vec::new(),
+    }
+  }
   pub fn empty() -> Self {
     LatexmlResponse {
       status_code: 0,
       status: String::new(),
       log: String::new(),
       result: String::new(),
+      assets: Vec::new(),
     }
   }
 }
 
+/// How often the memory of a server is measured, as walking /proc isn't free
+const MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How close to its recycling limits a server boots its warm standby, if enabled
+const STANDBY_WEAR: f64 = 0.9;
+
+/// Process-unique identifiers for servers, stable across port rotations
+static NEXT_SERVER_ID: AtomicUsize = AtomicUsize::new(1);
+
 #[derive(Debug)]
 pub struct Server {
+  id: usize,
   port: u16,
-  backup_port: u16,
+  // ports are allocated from this one onwards
+  base_port: u16,
   autoflush: usize,
   call_count: usize,
   cache_key: String,
   latexmls_exec: String,
   boot_options: Vec<(String, String)>,
+  job_timeout: Option<Duration>,
+  max_memory: Option<u64>,
+  max_age: Option<Duration>,
+  booted_at: Instant,
+  memory_checked_at: Instant,
+  memory_used: Option<u64>,
+  warm_standby: bool,
+  standby: Option<JoinHandle<Result<Server, String>>>,
+  init_response: Option<LatexmlResponse>,
   child_proc: Option<Child>,
   pub connection: Option<TcpStream>,
 }
 impl Server {
-  /// Boot a new latexmls server at a given port, with the specified options
+  /// Boot a new latexmls server at the first free port from `port` onwards,
+  /// with the specified options
   pub fn boot_at(
     latexmls_exec: String,
     port: u16,
@@ -57,14 +122,23 @@ impl Server {
     boot_options: Vec<(String, String)>,
   ) -> Result<Self, Box<dyn Error>> {
     let mut server = Server {
+      id: NEXT_SERVER_ID.fetch_add(1, Ordering::Relaxed),
       latexmls_exec,
-      port,
-      // should be a while before we have more than 200 latexmls processes on the same machine
-      backup_port: port + 200,
+      port: ports::global().allocate(port)?,
+      base_port: port,
       cache_key,
       boot_options,
       autoflush,
       call_count: 0,
+      job_timeout: None,
+      max_memory: None,
+      max_age: None,
+      booted_at: Instant::now(),
+      memory_checked_at: Instant::now(),
+      memory_used: None,
+      warm_standby: false,
+      standby: None,
+      init_response: None,
       connection: None,
       child_proc: None,
     };
@@ -73,23 +147,72 @@ impl Server {
     Ok(server)
   }
 
+  /// The process-unique identifier of this server, used in log events
+  pub fn id(&self) -> usize {
+    self.id
+  }
+  /// The port at which this server is currently listening
+  pub fn port(&self) -> u16 {
+    self.port
+  }
+
+  /// The response of latexmls to the initialization call of its last boot,
+  /// which loads the boot options and preloads
+  pub fn init_response(&self) -> Option<&LatexmlResponse> {
+    self.init_response.as_ref()
+  }
+
+  /// The wall-clock limit of a single conversion, measured by the runner
+  pub fn job_timeout(&self) -> Option<Duration> {
+    self.job_timeout
+  }
+  /// Sets the wall-clock limit of the following conversions, independently of the
+  /// `--timeout` of latexmls itself. A job exceeding it is abandoned, failing with
+  /// a `Timeout`, and the server is killed, to be respawned for the next job.
+  pub fn set_job_timeout(&mut self, job_timeout: Option<Duration>) {
+    self.job_timeout = job_timeout;
+  }
+
+  /// Sets the limits after which this server is recycled, as with `autoflush`:
+  /// the resident memory, in bytes, of its latexmls process tree, and its age since booting
+  pub fn set_recycle_limits(&mut self, max_memory: Option<u64>, max_age: Option<Duration>) {
+    self.max_memory = max_memory;
+    self.max_age = max_age;
+  }
+  /// Whether to boot a replacement process in the background as this server nears its
+  /// recycling limits, so that recycling doesn't stall its next conversion
+  pub fn set_warm_standby(&mut self, warm_standby: bool) {
+    self.warm_standby = warm_standby;
+  }
+  /// The resident memory, in bytes, of this server's latexmls process and its descendants
+  pub fn memory_usage(&self) -> Option<u64> {
+    self
+      .child_proc
+      .as_ref()
+      .and_then(|child| memory::tree_rss(child.id()))
+  }
+
   /// Convert a single job with a dedicated latexmls server, pinned to a port
   pub fn convert(&mut self, job: &str) -> Result<LatexmlResponse, Box<dyn Error>> {
+    self.convert_source(Source::Literal(job), None)
+  }
+
+  /// Same as `convert`, for a literal job or a document on disk. If a `destination` is given,
+  /// latexml writes the files it generates, e.g. images, relative to it
+  pub fn convert_source(
+    &mut self,
+    source: Source<'_>,
+    destination: Option<&Path>,
+  ) -> Result<LatexmlResponse, Box<dyn Error>> {
     self.ensure_server()?;
-    match self.call_latexmls(
-      &format!(
-        "cache_key={}&source=literal:{}",
-        self.cache_key,
-        encode(job)
-      ),
-      true,
-    ) {
+    let deadline = self.job_timeout.map(|limit| Instant::now() + limit);
+    match self.call_latexmls(&self.source_body(source, destination), true, deadline) {
       Ok(r) => Ok(r),
       Err(e) => {
         // close connection on error.
         self.terminate_proc();
         Err(e)
-      }
+      },
     }
   }
 
@@ -98,42 +221,70 @@ impl Server {
   /// The only resourceful choice is to see if the port is open & available for bind
   /// in which case we should be booting a server at it.
   pub fn ensure_server(&mut self) -> Result<(), Box<dyn Error>> {
-    if let Some(ref mut child) = self.child_proc {
-      // Check if reaped - e.g. via --expire
-      // in which case we can release the pid
-      if let Ok(Some(_)) = child.try_wait() {
-        self.child_proc = None;
-      }
+    self.release_expired();
+    let (wear, limit) = self.wear();
+    if self.warm_standby && self.standby.is_none() && wear >= STANDBY_WEAR {
+      self.boot_standby();
     }
-    if self.autoflush > 0 && self.call_count > self.autoflush {
-      // if autoflush was breached, rotate ports.
-      self.rotate_ports()?;
+    // a booted standby is held in reserve until the current process is worn out
+    if wear > 1.0 {
+      if self.standby.is_some() {
+        match self.swap_in_standby() {
+          // the standby may have expired while waiting, and is then rebooted at its port
+          Ok(()) => self.release_expired(),
+          Err(e) => {
+            warn!(server = self.id, port = self.port, error = ?e, "warm standby failed to boot");
+            self.rotate_ports()?;
+          },
+        }
+      } else {
+        // if autoflush, or another limit, was breached, rotate ports.
+        info!(
+          server = self.id,
+          port = self.port,
+          limit,
+          "recycling server"
+        );
+        self.rotate_ports()?;
+      }
     }
     if self.child_proc.is_none() {
       let child = Command::new(&self.latexmls_exec)
         .arg("--port")
-        .arg(&self.port.to_string())
+        .arg(self.port.to_string())
         .arg("--address")
         .arg("127.0.0.1")
         .arg("--autoflush")
-        .arg(&self.autoflush.to_string())
+        .arg(self.autoflush.to_string())
         .arg("--timeout")
         .arg("120")
         .arg("--expire")
         .arg("4")
         .spawn()?;
       self.child_proc = Some(child);
+      self.booted_at = Instant::now();
+      metrics::global().server_started();
 
       let half_a_second = time::Duration::from_millis(500);
       thread::sleep(half_a_second);
       // Try init twice, second time a waiting little longer -
       //  to make e.g. slow CI machines succeed smoothly.
       if let Err(e) = self.init_call() {
-        println!("init call for port {:?} needs to retry: {:?}", self.port, e);
+        warn!(server = self.id, port = self.port, error = ?e, "init call needs to retry");
         let a_second = time::Duration::from_millis(1000);
         thread::sleep(a_second);
         if let Err(e2) = self.init_call() {
-          println!("init retry on port {:?} failed: {:?}", self.port, e2);
+          error!(server = self.id, port = self.port, error = ?e2, "init retry failed");
+          // a latexmls which rejected its options exits right away
+          if let Some(Ok(Some(exit_status))) = self.child_proc.as_mut().map(Child::try_wait) {
+            return Err(
+              format!(
+                "latexmls exited while booting at port {} ({}), check its options",
+                self.port, exit_status
+              )
+              .into(),
+            );
+          }
           return Err(e2);
         }
       }
@@ -141,27 +292,114 @@ impl Server {
     Ok(())
   }
 
-  /// Rotates to the backup port, and resets connection and counters
+  /// Releases the latexmls process if it exited, e.g. via --expire
+  fn release_expired(&mut self) {
+    if let Some(ref mut child) = self.child_proc {
+      if let Ok(Some(_)) = child.try_wait() {
+        self.child_proc = None;
+        metrics::global().server_stopped();
+      }
+    }
+  }
+
+  /// How close this server is to being recycled, as the highest ratio of its call count,
+  /// age and memory usage to their limits, along with the name of that limit
+  fn wear(&mut self) -> (f64, &'static str) {
+    let mut wear = (0.0, "none");
+    if self.autoflush > 0 {
+      wear = (self.call_count as f64 / self.autoflush as f64, "autoflush");
+    }
+    if let Some(max_age) = self.max_age {
+      let age_wear = self.booted_at.elapsed().as_secs_f64() / max_age.as_secs_f64();
+      if age_wear > wear.0 {
+        wear = (age_wear, "max_age");
+      }
+    }
+    if let Some(max_memory) = self.max_memory {
+      if self.memory_checked_at.elapsed() >= MEMORY_CHECK_INTERVAL {
+        self.memory_checked_at = Instant::now();
+        self.memory_used = self.memory_usage();
+      }
+      if let Some(used) = self.memory_used {
+        let memory_wear = used as f64 / max_memory as f64;
+        if memory_wear > wear.0 {
+          wear = (memory_wear, "max_memory");
+        }
+      }
+    }
+    wear
+  }
+
+  /// Boots a replacement latexmls process in the background, at a freshly allocated port
+  fn boot_standby(&mut self) {
+    info!(server = self.id, port = self.port, "booting warm standby");
+    let latexmls_exec = self.latexmls_exec.clone();
+    let base_port = self.base_port;
+    let autoflush = self.autoflush;
+    let cache_key = self.cache_key.clone();
+    let boot_options = self.boot_options.clone();
+    self.standby = Some(thread::spawn(move || {
+      Server::boot_at(latexmls_exec, base_port, autoflush, cache_key, boot_options)
+        .map_err(|e| e.to_string())
+    }));
+  }
+
+  /// Takes over the process and port of the warm standby, waiting for it to boot if needed,
+  /// retiring the current latexmls process
+  fn swap_in_standby(&mut self) -> Result<(), Box<dyn Error>> {
+    let standby = match self.standby.take() {
+      Some(standby) => standby,
+      None => return Err("no warm standby was booted".into()),
+    };
+    let mut standby = standby.join().map_err(|_| "warm standby boot panicked")??;
+    info!(
+      server = self.id,
+      port = self.port,
+      to_port = standby.port,
+      "swapping in warm standby"
+    );
+    metrics::global().inc_rotations();
+    std::mem::swap(&mut self.port, &mut standby.port);
+    std::mem::swap(&mut self.child_proc, &mut standby.child_proc);
+    std::mem::swap(&mut self.connection, &mut standby.connection);
+    std::mem::swap(&mut self.init_response, &mut standby.init_response);
+    self.booted_at = standby.booted_at;
+    self.memory_checked_at = Instant::now();
+    self.memory_used = None;
+    self.call_count = 0;
+    // dropping the standby now terminates the retired process, and releases its port
+    Ok(())
+  }
+
+  /// Rotates to a freshly allocated port, and resets connection and counters
   pub fn rotate_ports(&mut self) -> Result<(), Box<dyn Error>> {
-    eprintln!(
-      "-- rotating port {} to port {}",
-      self.port, self.backup_port
+    // allocated while still holding the current port, so that the two differ
+    let new_port = ports::global().allocate(self.base_port)?;
+    info!(
+      server = self.id,
+      port = self.port,
+      to_port = new_port,
+      "rotating port"
     );
-    std::mem::swap(&mut self.port, &mut self.backup_port);
+    metrics::global().inc_rotations();
     self.call_count = 0;
     self.terminate_proc();
+    ports::global().release(std::mem::replace(&mut self.port, new_port));
     Ok(())
   }
 
-  /// Resamples ports, as latexmls is still not stable enough, and may need to be completely abandoned.
+  /// Resamples ports from a random start in the `from..to` range, and reboots the server there.
   /// Won't be done by the Harness, but some external applications may find it useful.
   pub fn resample_ports(&mut self, from: u16, to: u16) -> Result<(), Box<dyn Error>> {
-    let new_port: u16 = thread_rng().gen_range(from, to);
-    let new_backup = new_port + 200;
-    eprintln!("-- port resampling from {} to {}.", self.port, new_port);
-    self.port = new_port;
-    self.backup_port = new_backup;
+    let new_port = ports::global().allocate(thread_rng().gen_range(from, to))?;
+    info!(
+      server = self.id,
+      port = self.port,
+      to_port = new_port,
+      "resampling port"
+    );
     self.terminate_proc();
+    ports::global().release(std::mem::replace(&mut self.port, new_port));
     self.call_count = 0;
     self.ensure_server()
   }
@@ -181,16 +419,113 @@ impl Server {
         })
         .collect::<Vec<_>>()
         .join("&");
-    self.call_latexmls(&body, true)?;
+    self.init_response = Some(self.call_latexmls(&body, true, None)?);
     Ok(())
   }
 
+  /// The urlencoded request body for converting `source`. Documents are looked up
+  /// by their path, with their directory as the base and search path of the conversion,
+  /// so that their local inputs, bibliographies and graphics are found
+  pub(crate) fn source_body(&self, source: Source<'_>, destination: Option<&Path>) -> String {
+    let body = match source {
+      Source::Literal(job) => format!(
+        "cache_key={}&source=literal:{}",
+        self.cache_key,
+        encode(job)
+      ),
+      Source::Document(path) => {
+        let dir = path
+          .parent()
+          .map_or_else(|| Cow::Borrowed("."), Path::to_string_lossy);
+        format!(
+          "cache_key={}&source={}&base={}&path={}",
+          self.cache_key,
+          encode(&path.to_string_lossy()),
+          encode(&dir),
+          encode(&dir)
+        )
+      },
+    };
+    match destination {
+      Some(destination) => format!(
+        "{}&destination={}",
+        body,
+        encode(&destination.to_string_lossy())
+      ),
+      None => body,
+    }
+  }
+
+  /// The raw HTTP request posting `body` to this server
+  pub(crate) fn http_request(&self, body: &str) -> String {
+    let addr = format!("127.0.0.1:{}", self.port);
+    format!(
+      "POST {} HTTP/1.0
+Host: {}
+User-Agent: latexmlc
+Content-Type: application/x-www-form-urlencoded
+Content-Length: {}
+
+{}",
+      addr,
+      addr,
+      body.len(),
+      body
+    )
+  }
+
+  /// Extracts the payload from a raw HTTP response of latexmls
+  pub(crate) fn parse_response(
+    &self,
+    response_u8: &[u8],
+  ) -> Result<LatexmlResponse, ConversionError> {
+    let body_index = find_subsequence(response_u8, "\r\n\r\n".as_bytes()).unwrap_or(0);
+    if response_u8.is_empty() || body_index == 0 {
+      return Err(ConversionError::new(
+        FailureKind::EmptyResponse,
+        "response was empty.",
+      ));
+    }
+    let body_u8 = &response_u8[body_index + 4..];
+    // We need to assemble our own UTF-16 string, or glyphs such as π get garbled on follow-up IO
+    let payload: LatexmlResponse = match serde_json::from_slice(body_u8) {
+      Ok(json) => json,
+      Err(e) => {
+        warn!(
+          server = self.id,
+          port = self.port,
+          error = ?e,
+          body = ?String::from_utf8_lossy(body_u8),
+          "malformed latexmls response"
+        );
+        return Err(ConversionError::new(
+          FailureKind::MalformedResponse,
+          e.to_string(),
+        ));
+      },
+    };
+    trace!(
+      server = self.id,
+      port = self.port,
+      status_code = payload.status_code,
+      body_size = body_u8.len(),
+      "latexmls returned"
+    );
+    Ok(payload)
+  }
+
+  /// Counts a call towards the `autoflush` limit of this server
+  pub(crate) fn count_call(&mut self) {
+    self.call_count += 1;
+  }
+
   fn call_latexmls(
     &mut self,
     body: &str,
     allow_retry: bool,
+    deadline: Option<Instant>,
   ) -> Result<LatexmlResponse, Box<dyn Error>> {
-    self.call_count += 1;
+    self.count_call();
     let addr = format!("127.0.0.1:{}", self.port);
     let mut stream = match self.connection.take() {
       Some(stream) => stream,
@@ -205,53 +540,34 @@ impl Server {
               Ok(s) => s,
               Err(e) => {
                 return Err(e.into());
-              }
+              },
             }
-          }
+          },
         }
-      }
+      },
     };
     stream.set_nodelay(true)?;
-    let request = format!(
-      "POST {} HTTP/1.0
-Host: {}
-User-Agent: latexmlc
-Content-Type: application/x-www-form-urlencoded
-Content-Length: {}
-
-{}",
-      addr,
-      addr,
-      body.len(),
-      body
-    );
-    stream.write_all(request.as_bytes())?;
     let mut response_u8 = Vec::new();
-    // Array with a fixed size
-    stream.read_to_end(&mut response_u8)?;
-    let body_index = find_subsequence(&response_u8, "\r\n\r\n".as_bytes()).unwrap_or(0);
-    if response_u8.is_empty() || body_index == 0 {
-      return if allow_retry {
-        self.call_latexmls(body, false)
-      } else {
-        Err("response was empty.".into())
-      };
+    match deadline {
+      None => {
+        stream.set_write_timeout(None)?;
+        stream.set_read_timeout(None)?;
+        stream.write_all(self.http_request(body).as_bytes())?;
+        stream.read_to_end(&mut response_u8)?;
+      },
+      Some(deadline) => {
+        stream.set_write_timeout(Some(remaining(deadline)?))?;
+        stream.write_all(self.http_request(body).as_bytes())?;
+        read_to_end_by(&mut stream, &mut response_u8, deadline)?;
+      },
     }
-    let body_u8 = &response_u8[body_index + 4..];
-    // We need to assemble our own UTF-16 string, or glyphs such as π get garbled on follow-up IO
-    let payload: LatexmlResponse = match serde_json::from_slice(body_u8) {
-      Ok(json) => json,
-      Err(e) => {
-        println!("-- malformed {:?}: {:?}", e, std::str::from_utf8(body_u8));
-        LatexmlResponse::default()
-      }
+    let payload = match self.parse_response(&response_u8) {
+      Ok(payload) => payload,
+      Err(e) if e.kind == FailureKind::EmptyResponse && allow_retry => {
+        return self.call_latexmls(body, false, deadline);
+      },
+      Err(e) => return Err(e.into()),
     };
-    // println!(
-    //   "-- latexmls:{} returned status {} with body_size {}",
-    //   self.port,
-    //   payload.status_code,
-    //   body_u8.len()
-    // );
     // reuse the stream if we were OK
     if payload.status_code != 3 {
       self.connection = Some(stream);
@@ -260,16 +576,52 @@ Content-Length: {}
     }
     Ok(payload)
   }
-  fn terminate_proc(&mut self) {
+  pub(crate) fn terminate_proc(&mut self) {
     if let Some(ref mut stream) = self.connection {
       stream.shutdown(Shutdown::Both).unwrap();
     }
-    if let Some(ref mut proc) = self.child_proc {
+    if let Some(mut proc) = self.child_proc.take() {
       if let Ok(Some(_)) = proc.try_wait() {
       } else {
         proc.kill().unwrap();
         proc.wait().unwrap();
       }
+      metrics::global().server_stopped();
+    }
+  }
+}
+
+/// The time left until `deadline`, or a `Timeout` if it has passed
+fn remaining(deadline: Instant) -> Result<Duration, ConversionError> {
+  let left = deadline.saturating_duration_since(Instant::now());
+  if left.is_zero() {
+    Err(ConversionError::new(
+      FailureKind::Timeout,
+      "conversion exceeded the per-job time limit",
+    ))
+  } else {
+    Ok(left)
+  }
+}
+
+/// Reads a response to its end, giving up once `deadline` has passed
+fn read_to_end_by(
+  stream: &mut TcpStream,
+  buffer: &mut Vec<u8>,
+  deadline: Instant,
+) -> Result<(), Box<dyn Error>> {
+  let mut chunk = [0; 8192];
+  loop {
+    stream.set_read_timeout(Some(remaining(deadline)?))?;
+    match stream.read(&mut chunk) {
+      Ok(0) => return Ok(()),
+      Ok(size) => buffer.extend_from_slice(&chunk[..size]),
+      Err(e)
+        if matches!(
+          e.kind(),
+          io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+        ) => {},
+      Err(e) => return Err(e.into()),
     }
   }
 }
@@ -282,6 +634,11 @@ fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
 
 impl Drop for Server {
   fn drop(&mut self) {
-    self.terminate_proc()
+    if let Some(standby) = self.standby.take() {
+      // the standby is terminated as it is dropped
+      let _ = standby.join();
+    }
+    self.terminate_proc();
+    ports::global().release(self.port);
   }
 }
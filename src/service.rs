@@ -0,0 +1,243 @@
+//! A local HTTP conversion service, exposing a `Harness` pool to other applications.
+//!
+//! Endpoints:
+//!  - `POST /convert` with a JSON string (or a raw TeX body) converts a single job
+//!    and responds with a `LatexmlResponse` JSON object
+//!  - `POST /convert` with a JSON array of strings converts all jobs in parallel
+//!    and responds with a JSON array of `LatexmlResponse` objects, in the same order
+//!  - `GET /health` reports the state of the service
+//!
+//! Each connection is handled by a worker of its own, converting with the shared harness,
+//! so that a slow job only holds up its own request, and the servers it occupies.
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::result::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crossbeam::channel::{bounded, TrySendError};
+use serde_json::json;
+use tracing::{debug, error, info, warn};
+
+use crate::metrics;
+use crate::Harness;
+
+/// Limits for the conversion service
+#[derive(Debug, Clone)]
+pub struct ServiceOptions {
+  /// Number of HTTP connections handled concurrently
+  pub max_connections: usize,
+  /// Number of accepted connections allowed to wait for a handler,
+  /// beyond which new requests are rejected with "503 Service Unavailable"
+  pub queue_size: usize,
+  /// Largest accepted request body, in bytes
+  pub max_body_size: usize,
+}
+impl Default for ServiceOptions {
+  fn default() -> Self {
+    ServiceOptions {
+      max_connections: 64,
+      queue_size: 256,
+      max_body_size: 16 * 1024 * 1024,
+    }
+  }
+}
+
+/// Serves conversions with `harness` over HTTP at `address` (e.g. "127.0.0.1:8080").
+/// Blocks for the lifetime of the service.
+pub fn serve(
+  harness: Harness,
+  address: &str,
+  options: ServiceOptions,
+) -> Result<(), Box<dyn Error>> {
+  let listener = TcpListener::bind(address)?;
+  info!(address, ?options, "conversion service listening");
+  let harness = Arc::new(harness);
+  // numbers the jobs of all requests, as lines of an input file would be
+  let job_count = Arc::new(AtomicUsize::new(1));
+  let (connection_sender, connection_receiver) = bounded::<TcpStream>(options.queue_size);
+  let mut workers = Vec::with_capacity(options.max_connections);
+  for worker in 0..options.max_connections {
+    let connections = connection_receiver.clone();
+    let harness = harness.clone();
+    let job_count = job_count.clone();
+    let max_body_size = options.max_body_size;
+    workers.push(
+      thread::Builder::new()
+        .name(format!("service-worker-{}", worker))
+        .spawn(move || {
+          for stream in connections.iter() {
+            if let Err(e) = handle_connection(stream, &harness, &job_count, max_body_size) {
+              debug!(error = ?e, "failed to handle connection");
+            }
+          }
+        })?,
+    );
+  }
+  drop(connection_receiver);
+  for stream in listener.incoming().flatten() {
+    match connection_sender.try_send(stream) {
+      Ok(()) => {},
+      Err(TrySendError::Full(stream)) => {
+        warn!("request queue is full, rejecting connection");
+        let _ = respond(
+          stream,
+          "503 Service Unavailable",
+          &json!({"error": "queue is full"}),
+        );
+      },
+      Err(TrySendError::Disconnected(_)) => break,
+    }
+  }
+  drop(connection_sender);
+  for worker in workers {
+    let _ = worker.join();
+  }
+  Ok(())
+}
+
+fn handle_connection(
+  stream: TcpStream,
+  harness: &Harness,
+  job_count: &AtomicUsize,
+  max_body_size: usize,
+) -> Result<(), Box<dyn Error>> {
+  stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+  let mut reader = BufReader::new(stream);
+  let mut request_line = String::new();
+  reader.read_line(&mut request_line)?;
+  let mut content_length = Ok(0);
+  let mut line = String::new();
+  while reader.read_line(&mut line)? > 0 {
+    if line == "\r\n" || line == "\n" {
+      break;
+    }
+    if let Some((name, value)) = line.split_once(':') {
+      if name.trim().eq_ignore_ascii_case("content-length") {
+        let value = value.trim();
+        // an invalid header isn't overridden by a later one
+        content_length = content_length.and_then(|_| value.parse().map_err(|_| value.to_string()));
+      }
+    }
+    line.clear();
+  }
+  let content_length: usize = match content_length {
+    Ok(length) => length,
+    Err(value) => {
+      return respond(
+        reader.into_inner(),
+        "400 Bad Request",
+        &json!({ "error": format!("invalid Content-Length {:?}", value) }),
+      )
+    },
+  };
+  let mut request_parts = request_line.split_whitespace();
+  let method = request_parts.next().unwrap_or_default().to_string();
+  let path = request_parts.next().unwrap_or_default().to_string();
+  match (method.as_str(), path.as_str()) {
+    ("GET", "/health") => {
+      let metrics = metrics::global();
+      respond(
+        reader.into_inner(),
+        "200 OK",
+        &json!({
+          "status": "ok",
+          "active_servers": metrics.active_servers(),
+          "queue_depth": metrics.queue_depth(),
+        }),
+      )
+    },
+    ("POST", "/convert") => {
+      if content_length > max_body_size {
+        return respond(
+          reader.into_inner(),
+          "413 Payload Too Large",
+          &json!({ "error": format!("request body exceeds {} bytes", max_body_size) }),
+        );
+      }
+      let mut body = vec![0; content_length];
+      reader.read_exact(&mut body)?;
+      let stream = reader.into_inner();
+      let (jobs, is_batch) = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(serde_json::Value::Array(values)) => {
+          match values
+            .into_iter()
+            .map(|value| match value {
+              serde_json::Value::String(job) => Ok(job),
+              _ => Err("array entries must be strings"),
+            })
+            .collect::<Result<Vec<_>, _>>()
+          {
+            Ok(jobs) => (jobs, true),
+            Err(e) => return respond(stream, "400 Bad Request", &json!({ "error": e })),
+          }
+        },
+        Ok(serde_json::Value::String(job)) => (vec![job], false),
+        // any other body is taken verbatim, as a single TeX job
+        _ => match String::from_utf8(body) {
+          Ok(job) => (vec![job], false),
+          Err(_) => {
+            return respond(
+              stream,
+              "400 Bad Request",
+              &json!({"error": "request body must be UTF-8"}),
+            )
+          },
+        },
+      };
+      let first_job = job_count.fetch_add(jobs.len(), Ordering::SeqCst);
+      // a single job converts right on this worker, a batch on the harness' thread pool
+      let converted = if is_batch {
+        harness
+          .convert_iterator(jobs.iter().map(String::as_str), first_job)
+          .map(|responses| json!(responses))
+      } else {
+        harness
+          .convert_job(first_job, &jobs[0])
+          .map(|response| json!(response))
+          .map_err(|e| e.into())
+      };
+      match converted {
+        Ok(responses) => respond(stream, "200 OK", &responses),
+        Err(e) => {
+          error!(error = %e, "conversion service can no longer convert");
+          respond(
+            stream,
+            "503 Service Unavailable",
+            &json!({ "error": e.to_string() }),
+          )
+        },
+      }
+    },
+    (_, "/health") | (_, "/convert") => respond(
+      reader.into_inner(),
+      "405 Method Not Allowed",
+      &json!({"error": "method not allowed"}),
+    ),
+    _ => respond(
+      reader.into_inner(),
+      "404 Not Found",
+      &json!({"error": "not found"}),
+    ),
+  }
+}
+
+fn respond<T: serde::Serialize + ?Sized>(
+  mut stream: TcpStream,
+  status: &str,
+  payload: &T,
+) -> Result<(), Box<dyn Error>> {
+  let body = serde_json::to_vec(payload)?;
+  write!(
+    stream,
+    "HTTP/1.0 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+    status,
+    body.len()
+  )?;
+  stream.write_all(&body)?;
+  stream.flush()?;
+  Ok(())
+}
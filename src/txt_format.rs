@@ -0,0 +1,298 @@
+//! The layout of TXT inputs: how their records are framed, by default one TeX job per line,
+//! and how they are decoded, reporting the position of records which can't be decoded.
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::result::Result;
+use std::str::FromStr;
+
+use encoding_rs::{DecoderResult, Encoding, UTF_8};
+
+/// How the records of a TXT input are separated
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Framing {
+  /// Each line is a record (the default)
+  #[default]
+  Line,
+  /// Records span consecutive lines, separated by one or more blank lines
+  BlankLine,
+  /// Records are separated by NUL bytes, and may contain any line, including blank ones
+  Nul,
+  /// Records span the lines between occurrences of this separator line
+  Separator(String),
+}
+
+impl FromStr for Framing {
+  type Err = String;
+  fn from_str(framing: &str) -> Result<Self, Self::Err> {
+    match framing {
+      "line" => Ok(Framing::Line),
+      "blank_line" => Ok(Framing::BlankLine),
+      "nul" => Ok(Framing::Nul),
+      other => Err(format!(
+        "unknown TXT framing {:?}, expected one of: line, blank_line, nul",
+        other
+      )),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct TxtOptions {
+  /// The encoding of TXT inputs (and of the failures files written for them), UTF-8 by default.
+  /// Must be ASCII-compatible, as records are split at LF or NUL bytes
+  pub encoding: &'static Encoding,
+  /// Whether bytes which are invalid in `encoding` are decoded as U+FFFD,
+  /// rather than failing their record with a `malformed_input` status
+  pub lossy: bool,
+  /// How records are separated
+  pub framing: Framing,
+}
+
+impl Default for TxtOptions {
+  fn default() -> Self {
+    TxtOptions {
+      encoding: UTF_8,
+      lossy: false,
+      framing: Framing::Line,
+    }
+  }
+}
+
+impl TxtOptions {
+  /// Writes `text` as a single record, framed and encoded as per these options
+  pub fn write_record<W: Write>(&self, writer: &mut W, text: &str) -> io::Result<()> {
+    let (bytes, _, _) = self.encoding.encode(text);
+    writer.write_all(&bytes)?;
+    match self.framing {
+      Framing::Line => writer.write_all(b"\n"),
+      Framing::BlankLine => writer.write_all(b"\n\n"),
+      Framing::Nul => writer.write_all(b"\0"),
+      Framing::Separator(ref separator) => {
+        let (separator, _, _) = self.encoding.encode(separator);
+        writer.write_all(b"\n")?;
+        writer.write_all(&separator)?;
+        writer.write_all(b"\n")
+      },
+    }
+  }
+}
+
+/// Parses an ASCII-compatible encoding by its WHATWG label, e.g. `utf-8`, `latin1` or `gbk`
+pub fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+  match Encoding::for_label(label.trim().as_bytes()) {
+    Some(encoding) if encoding.is_ascii_compatible() => Ok(encoding),
+    Some(encoding) => Err(format!(
+      "unsupported input encoding {}, TXT inputs must be ASCII-compatible",
+      encoding.name()
+    )),
+    None => Err(format!("unknown input encoding {:?}", label)),
+  }
+}
+
+/// A record of a TXT input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxtRecord {
+  /// The 1-based line at which the record starts
+  pub line: usize,
+  /// The decoded record, without its line terminators or separators
+  pub text: String,
+}
+
+/// A record which isn't valid in the encoding of its input
+#[derive(Debug, Clone)]
+pub struct MalformedLine {
+  /// The 1-based line number of the first invalid byte
+  pub line: usize,
+  /// The 0-based byte offset of the first invalid byte in the input
+  pub byte: u64,
+  pub encoding: &'static Encoding,
+}
+impl fmt::Display for MalformedLine {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "malformed TXT line at line {}, byte {}: invalid {}",
+      self.line,
+      self.byte,
+      self.encoding.name()
+    )
+  }
+}
+impl Error for MalformedLine {}
+
+/// The decoded records of a TXT input
+pub struct Records<R> {
+  reader: R,
+  options: TxtOptions,
+  line: usize,
+  byte: u64,
+}
+
+/// A chunk of the input up to a delimiter byte, which it doesn't include
+struct Chunk {
+  bytes: Vec<u8>,
+  line: usize,
+  byte: u64,
+}
+
+/// Reads the records of `reader`, framed and decoded as per `options`
+pub fn records<R: BufRead>(reader: R, options: TxtOptions) -> Records<R> {
+  Records {
+    reader,
+    options,
+    line: 1,
+    byte: 0,
+  }
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+  /// Fails on I/O errors, and succeeds with either the record, or where it is malformed
+  type Item = io::Result<Result<TxtRecord, MalformedLine>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    match self.options.framing {
+      Framing::Line => self.next_line().transpose(),
+      Framing::Nul => self.next_nul_record().transpose(),
+      Framing::BlankLine | Framing::Separator(_) => self.next_multiline_record().transpose(),
+    }
+  }
+}
+
+impl<R: BufRead> Records<R> {
+  /// Reads up to the next `delimiter`, or the end of the input
+  fn read_chunk(&mut self, delimiter: u8) -> io::Result<Option<Chunk>> {
+    let mut bytes = Vec::new();
+    let read = self.reader.read_until(delimiter, &mut bytes)?;
+    if read == 0 {
+      return Ok(None);
+    }
+    let chunk_line = self.line;
+    let chunk_byte = self.byte;
+    self.line += bytes.iter().filter(|byte| **byte == b'\n').count();
+    self.byte += read as u64;
+    if bytes.last() == Some(&delimiter) {
+      bytes.pop();
+      if delimiter == b'\n' && bytes.ends_with(b"\r") {
+        bytes.pop();
+      }
+    }
+    Ok(Some(Chunk {
+      bytes,
+      line: chunk_line,
+      byte: chunk_byte,
+    }))
+  }
+
+  /// Decodes a chunk, or fails with the position of its first invalid byte
+  fn decode(&self, chunk: &Chunk) -> Result<String, MalformedLine> {
+    decode(&self.options, &chunk.bytes).map_err(|offset| MalformedLine {
+      line: chunk.line
+        + chunk.bytes[..offset]
+          .iter()
+          .filter(|byte| **byte == b'\n')
+          .count(),
+      byte: chunk.byte + offset as u64,
+      encoding: self.options.encoding,
+    })
+  }
+
+  fn next_line(&mut self) -> io::Result<Option<Result<TxtRecord, MalformedLine>>> {
+    Ok(self.read_chunk(b'\n')?.map(|chunk| {
+      self.decode(&chunk).map(|text| TxtRecord {
+        line: chunk.line,
+        text,
+      })
+    }))
+  }
+
+  fn next_nul_record(&mut self) -> io::Result<Option<Result<TxtRecord, MalformedLine>>> {
+    while let Some(mut chunk) = self.read_chunk(b'\0')? {
+      // the line break of a NUL terminator on a line of its own, e.g. as "\0\n"
+      let line_break = [&b"\r\n"[..], b"\n"]
+        .into_iter()
+        .find(|line_break| chunk.byte > 0 && chunk.bytes.starts_with(line_break));
+      if let Some(line_break) = line_break {
+        chunk.bytes.drain(..line_break.len());
+        chunk.line += 1;
+        chunk.byte += line_break.len() as u64;
+      }
+      // empty records, e.g. the final line break of a file whose last record is NUL-terminated
+      if chunk.bytes.iter().all(u8::is_ascii_whitespace) {
+        continue;
+      }
+      return Ok(Some(self.decode(&chunk).map(|text| TxtRecord {
+        line: chunk.line,
+        text,
+      })));
+    }
+    Ok(None)
+  }
+
+  /// The next record of consecutive lines, up to a blank or separator line
+  fn next_multiline_record(&mut self) -> io::Result<Option<Result<TxtRecord, MalformedLine>>> {
+    let mut first_line = None;
+    let mut lines: Vec<String> = Vec::new();
+    let mut malformed = None;
+    while let Some(chunk) = self.read_chunk(b'\n')? {
+      let decoded = self.decode(&chunk);
+      let ends_record = match (&self.options.framing, &decoded) {
+        (Framing::BlankLine, Ok(text)) => text.trim().is_empty(),
+        (Framing::Separator(separator), Ok(text)) => text == separator,
+        _ => false,
+      };
+      if ends_record {
+        if malformed.is_none() && lines.iter().all(|line| line.trim().is_empty()) {
+          // leading and repeated blank or separator lines don't make up a record
+          first_line = None;
+          lines.clear();
+          continue;
+        }
+        break;
+      }
+      first_line.get_or_insert(chunk.line);
+      match decoded {
+        Ok(text) => lines.push(text),
+        Err(e) => {
+          malformed.get_or_insert(e);
+        },
+      }
+    }
+    if malformed.is_none() && lines.iter().all(|line| line.trim().is_empty()) {
+      // e.g. the blank lines after the last separator
+      return Ok(None);
+    }
+    Ok(first_line.map(|line| match malformed {
+      Some(e) => Err(e),
+      None => Ok(TxtRecord {
+        line,
+        text: lines.join("\n"),
+      }),
+    }))
+  }
+}
+
+/// Decodes `bytes`, or fails with the offset of their first invalid byte
+fn decode(options: &TxtOptions, bytes: &[u8]) -> Result<String, usize> {
+  if options.lossy {
+    let (text, _) = options.encoding.decode_without_bom_handling(bytes);
+    return Ok(text.into_owned());
+  }
+  let mut decoder = options.encoding.new_decoder_without_bom_handling();
+  let mut text = String::new();
+  let mut offset = 0;
+  loop {
+    // reserve enough for the decoder to make progress, in any encoding
+    text.reserve(3 * (bytes.len() - offset) + 16);
+    let (result, read) =
+      decoder.decode_to_string_without_replacement(&bytes[offset..], &mut text, true);
+    offset += read;
+    match result {
+      DecoderResult::InputEmpty => return Ok(text),
+      DecoderResult::OutputFull => {},
+      DecoderResult::Malformed(invalid, unread) => {
+        return Err(offset - invalid as usize - unread as usize)
+      },
+    }
+  }
+}
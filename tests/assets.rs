@@ -0,0 +1,61 @@
+mod common;
+
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn collect_generated_assets() {
+  common::use_latexmls_double();
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [
+      ("whatsin", "math"),
+      ("whatsout", "math"),
+      ("mathimages", ""),
+    ]
+    .iter()
+    .map(|(x, y)| (x.to_string(), y.to_string()))
+    .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+
+  let dir = "tests/scratch/assets";
+  fs::create_dir_all(dir).unwrap();
+  let input = format!("{}/input.txt", dir);
+  let output = format!("{}/output.csv", dir);
+  let log = format!("{}/output.log", dir);
+  let assets_dir = format!("{}/files", dir);
+  // the test double of latexmls generates files for jobs asking for an IMAGE
+  fs::write(&input, "a+b\n\\IMAGE{x}\n").unwrap();
+  harness.assets_dir = Some(assets_dir.clone());
+
+  let converted = harness.convert_file(&input, &output, &log);
+  assert!(converted.is_ok(), "{:?}", converted);
+  let rows: Vec<Vec<String>> = csv::ReaderBuilder::new()
+    .has_headers(false)
+    .from_path(&output)
+    .unwrap()
+    .into_records()
+    .map(|record| record.unwrap().iter().map(str::to_string).collect())
+    .collect();
+  assert_eq!(rows[0][1], "");
+  assert_eq!(rows[1][1], "2/images/x2.svg;2/x1.png");
+  for asset in rows[1][1].split(';') {
+    assert!(Path::new(&assets_dir).join(asset).is_file(), "{}", asset);
+  }
+  // jobs without assets leave no working directory behind
+  assert!(!Path::new(&assets_dir).join("1").exists());
+
+  // without options generating files, jobs don't convert in directories of their own
+  fs::remove_dir_all(&assets_dir).unwrap();
+  harness.generates_assets = false;
+  let converted = harness.convert_file(&input, &output, &log);
+  assert!(converted.is_ok(), "{:?}", converted);
+  assert!(fs::read_to_string(&output).unwrap().ends_with(",\n"));
+  assert!(!Path::new(&assets_dir).join("2").exists());
+}
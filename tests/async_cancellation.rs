@@ -0,0 +1,49 @@
+#![cfg(feature = "async")]
+mod common;
+
+use latexml_runner::{AsyncHarness, Harness};
+use rand::prelude::*;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancel_in_flight_conversion() {
+  common::use_latexmls_double();
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  // a pool of a single server, so that the cancelled job's server is the only one left
+  let single = rayon::ThreadPoolBuilder::new()
+    .num_threads(1)
+    .build()
+    .unwrap();
+  let harness_result = single.install(|| {
+    Harness::new(
+      from_port,
+      0,
+      [("whatsin", "math"), ("whatsout", "math")]
+        .iter()
+        .map(|(x, y)| (x.to_string(), y.to_string()))
+        .collect(),
+    )
+    .map_err(|e| e.to_string())
+  });
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let harness = AsyncHarness::from(harness_result.unwrap());
+
+  // the test double holds on to a HANG job for 30 seconds, long after its request was sent
+  let hanging = harness.clone();
+  let in_flight = tokio::spawn(async move { hanging.convert("\\HANG").await });
+  tokio::time::sleep(Duration::from_secs(1)).await;
+
+  // a conversion waiting for the busy server stops waiting once cancelled
+  let waiting = tokio::time::timeout(Duration::from_secs(1), harness.convert("x^2")).await;
+  assert!(waiting.is_err(), "{:?}", waiting);
+
+  in_flight.abort();
+  assert!(in_flight.await.unwrap_err().is_cancelled());
+
+  // the cancelled conversion returned its server to the pool, ready for the next job
+  let response = tokio::time::timeout(Duration::from_secs(20), harness.convert("a+b")).await;
+  assert!(response.is_ok(), "the server was not returned to the pool");
+  let response = response.unwrap();
+  assert!(response.is_ok(), "{:?}", response);
+  assert_eq!(response.unwrap().result, "<m>a+b</m>");
+}
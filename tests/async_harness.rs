@@ -0,0 +1,31 @@
+#![cfg(feature = "async")]
+use latexml_runner::{AsyncHarness, Harness};
+use rand::prelude::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn convert_concurrently() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let harness = AsyncHarness::from(harness_result.unwrap());
+
+  let tasks: Vec<_> = ["E=mc^2", "a+b", "\\sqrt{x}", "\\frac{1}{2}"]
+    .iter()
+    .map(|job| {
+      let handle = harness.clone();
+      tokio::spawn(async move { handle.convert(job).await })
+    })
+    .collect();
+  for task in tasks {
+    let response = task.await.unwrap();
+    assert!(response.is_ok(), "{:?}", response);
+    assert!(response.unwrap().status_code < 3);
+  }
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn report_boot_warnings() {
+  common::use_latexmls_double();
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let harness = harness_result.unwrap();
+  let report = harness.boot_report();
+  assert_eq!(report.servers.len(), rayon::current_num_threads());
+  assert!(report.status_code() < 2, "{:?}", report);
+  for boot in report.servers.iter() {
+    assert!(boot.port >= from_port);
+  }
+}
+
+#[test]
+fn report_deprecated_preloads() {
+  common::use_latexmls_double();
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  // the test double of latexmls warns about preloads containing "deprecated"
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [
+      ("whatsin", "math"),
+      ("whatsout", "math"),
+      ("preload", "deprecated_package.sty"),
+    ]
+    .iter()
+    .map(|(x, y)| (x.to_string(), y.to_string()))
+    .collect(),
+  );
+  // a warning doesn't prevent the servers from booting
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let harness = harness_result.unwrap();
+  let report = harness.boot_report();
+  assert!(!report.is_clean(), "{:?}", report);
+  assert_eq!(report.status_code(), 1);
+  // every server logs the same warning, which is reported once
+  assert_eq!(
+    report.messages(),
+    vec!["Warning:deprecated:deprecated_package.sty Package deprecated_package.sty is deprecated"]
+  );
+}
+
+#[test]
+fn strict_rejects_boot_warnings() {
+  common::use_latexmls_double();
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let dir = "tests/scratch/boot_report";
+  fs::create_dir_all(dir).unwrap();
+  let input = format!("{}/input.txt", dir);
+  let output = format!("{}/output.csv", dir);
+  fs::write(&input, "a+b\n").unwrap();
+
+  let run = Command::new(env!("CARGO_BIN_EXE_latexml_runner"))
+    .args(["--from_port", &from_port.to_string()])
+    .args(["--input_file", &input, "--output_file", &output])
+    .args(["--whatsin", "math", "--whatsout", "math"])
+    .args(["--preload", "deprecated_package.sty", "--strict"])
+    .output()
+    .unwrap();
+  assert!(!run.status.success());
+  let stderr = String::from_utf8_lossy(&run.stderr);
+  assert!(stderr.contains("--strict makes fatal"), "{}", stderr);
+  assert!(
+    stderr.contains("Package deprecated_package.sty is deprecated"),
+    "{}",
+    stderr
+  );
+  assert!(!std::path::Path::new(&output).exists());
+}
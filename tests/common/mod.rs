@@ -0,0 +1,21 @@
+use std::env;
+use std::path::Path;
+use std::sync::Once;
+
+static DOUBLE_ON_PATH: Once = Once::new();
+
+/// Puts the test double of latexmls in `tests/double` first on the PATH, so that the servers
+/// booted by this test binary fail, crash, hang or generate files on demand.
+/// Must be called before booting a `Harness`, by every test of the binary.
+pub fn use_latexmls_double() {
+  DOUBLE_ON_PATH.call_once(|| {
+    let double = Path::new(env!("CARGO_MANIFEST_DIR"))
+      .join("tests")
+      .join("double");
+    let mut paths = vec![double];
+    if let Some(path) = env::var_os("PATH") {
+      paths.extend(env::split_paths(&path));
+    }
+    env::set_var("PATH", env::join_paths(paths).unwrap());
+  });
+}
@@ -0,0 +1,64 @@
+use latexml_runner::compression;
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::fs;
+use std::io::{Read, Write};
+
+fn read_to_string(path: &str) -> String {
+  let mut contents = String::new();
+  compression::open(path)
+    .unwrap()
+    .read_to_string(&mut contents)
+    .unwrap();
+  contents
+}
+
+#[test]
+fn compressed_inputs_and_outputs() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let harness = harness_result.unwrap();
+
+  let dir = "tests/scratch/compression";
+  fs::create_dir_all(dir).unwrap();
+  // the results of an uncompressed run, which compressed runs should reproduce
+  let (plain_input, plain_output, plain_log) = (
+    format!("{}/formulas.txt", dir),
+    format!("{}/plain_result.csv", dir),
+    format!("{}/plain.log", dir),
+  );
+  fs::write(&plain_input, "a+b\nx^2\n").unwrap();
+  let converted = harness.convert_file(&plain_input, &plain_output, &plain_log);
+  assert!(converted.is_ok(), "{:?}", converted);
+  let plain_output = fs::read_to_string(&plain_output).unwrap();
+  let plain_log = fs::read_to_string(&plain_log).unwrap();
+  assert_eq!(plain_log.lines().count(), 2);
+
+  for (input, output, log) in [
+    ("formulas.txt.gz", "result.csv.zst", "formulas.log.bz2"),
+    ("formulas.csv.zst", "result.csv.bz2", "formulas.log.gz"),
+    ("formulas.csv.bz2", "result.csv", "formulas.log"),
+  ] {
+    let (input, output, log) = (
+      format!("{}/{}", dir, input),
+      format!("{}/{}", dir, output),
+      format!("{}/{}", dir, log),
+    );
+    let mut writer = compression::create(&input).unwrap();
+    writer.write_all(b"a+b\nx^2\n").unwrap();
+    writer.finish().unwrap();
+
+    let converted = harness.convert_file(&input, &output, &log);
+    assert!(converted.is_ok(), "{}: {:?}", input, converted);
+    assert_eq!(read_to_string(&output), plain_output, "{}", input);
+    assert_eq!(read_to_string(&log), plain_log, "{}", input);
+  }
+}
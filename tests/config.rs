@@ -0,0 +1,78 @@
+use latexml_runner::config;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn config_to_arguments() {
+  let dir = "tests/scratch/config";
+  fs::create_dir_all(dir).unwrap();
+  let toml_path = format!("{}/runner.toml", dir);
+  fs::write(
+    &toml_path,
+    "from_port = 3334\npreload = [\"LaTeX.pool\", \"amsmath.sty\"]\nwhatsin = \"math\"\npmml = true\ncmml = false\n",
+  )
+  .unwrap();
+  let yaml_path = format!("{}/runner.yaml", dir);
+  fs::write(
+    &yaml_path,
+    "from_port: 3334\npreload:\n  - LaTeX.pool\n  - amsmath.sty\nwhatsin: math\npmml: true\ncmml: false\n",
+  )
+  .unwrap();
+
+  let from_toml = config::load(&toml_path).unwrap();
+  let from_yaml = config::load(&yaml_path).unwrap();
+  assert_eq!(from_toml, from_yaml);
+
+  // flags given on the command line are left out
+  let arguments = config::to_arguments(&from_toml, |flag| flag == "whatsin").unwrap();
+  assert_eq!(
+    arguments,
+    vec![
+      "--from_port=3334",
+      "--preload=LaTeX.pool",
+      "--preload=amsmath.sty",
+      "--pmml",
+    ]
+  );
+
+  let rendered = config::render(&from_toml).unwrap();
+  assert!(
+    rendered.contains("preload = [\"LaTeX.pool\", \"amsmath.sty\"]"),
+    "{}",
+    rendered
+  );
+  assert!(config::load("tests/scratch/config/missing.toml").is_err());
+}
+
+#[test]
+fn print_effective_config() {
+  let dir = "tests/scratch/config";
+  fs::create_dir_all(dir).unwrap();
+  let config_path = format!("{}/print.toml", dir);
+  fs::write(
+    &config_path,
+    "from_port = 3334\nautoflush = 100\nwhatsin = \"math\"\n",
+  )
+  .unwrap();
+  let print_config = |config: &str, arguments: &[&str]| {
+    let run = Command::new(env!("CARGO_BIN_EXE_latexml_runner"))
+      .args(["--config", config, "--print-config"])
+      .args(arguments)
+      .output()
+      .unwrap();
+    assert!(run.status.success(), "{:?}", run);
+    String::from_utf8(run.stdout).unwrap()
+  };
+
+  // the command line takes precedence over the config file, and numbers are printed as such
+  let printed = print_config(&config_path, &["--from_port", "4000"]);
+  let printed_path = format!("{}/printed.toml", dir);
+  fs::write(&printed_path, &printed).unwrap();
+  let effective = config::load(&printed_path).unwrap();
+  assert_eq!(effective["from_port"], 4000, "{}", printed);
+  assert_eq!(effective["autoflush"], 100, "{}", printed);
+  assert_eq!(effective["whatsin"], "math", "{}", printed);
+
+  // the printed configuration is a config file of its own, to the same effect
+  assert_eq!(print_config(&printed_path, &[]), printed);
+}
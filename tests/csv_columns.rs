@@ -0,0 +1,115 @@
+use latexml_runner::csv_format::{Column, CsvOptions};
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::fs;
+
+#[test]
+fn select_and_passthrough_columns() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+  harness.csv = CsvOptions {
+    has_headers: true,
+    tex_column: Some("tex".parse().unwrap()),
+    passthrough_columns: vec![Column::Name("id".to_string()), Column::Index(2)],
+    ..CsvOptions::default()
+  };
+
+  let output = "tests/scratch/columns/formulas_result.csv";
+  let converted = harness.convert_file(
+    "tests/data/columns/formulas.csv",
+    output,
+    "tests/scratch/columns/formulas.log",
+  );
+  assert!(converted.is_ok(), "{:?}", converted);
+  let rows: Vec<Vec<String>> = csv::ReaderBuilder::new()
+    .has_headers(false)
+    .from_path(output)
+    .unwrap()
+    .into_records()
+    .map(|record| record.unwrap().iter().map(str::to_string).collect())
+    .collect();
+  assert_eq!(rows.len(), 3);
+  assert_eq!(rows[1][0], "2");
+  assert_eq!(rows[1][1], "http://b");
+  assert!(rows[1][2].contains('y'), "{:?}", rows[1]);
+
+  // an unknown column fails the conversion, before truncating the outputs of earlier runs
+  let previous = fs::read_to_string(output).unwrap();
+  harness.csv.tex_column = Some(Column::Name("formula".to_string()));
+  assert!(harness
+    .convert_file(
+      "tests/data/columns/formulas.csv",
+      output,
+      "tests/scratch/columns/formulas.log"
+    )
+    .is_err());
+  assert_eq!(fs::read_to_string(output).unwrap(), previous);
+  // as does a column past the width of the header row
+  harness.csv.tex_column = Some(Column::Index(3));
+  let out_of_range = harness.convert_file(
+    "tests/data/columns/formulas.csv",
+    output,
+    "tests/scratch/columns/formulas.log",
+  );
+  let message = out_of_range.unwrap_err().to_string();
+  assert!(message.contains("out of range"), "{}", message);
+  assert_eq!(fs::read_to_string(output).unwrap(), previous);
+
+  // without a header row, the rows too short to hold the column are malformed
+  harness.csv = CsvOptions {
+    tex_column: Some(Column::Index(3)),
+    ..CsvOptions::default()
+  };
+  let converted = harness.convert_file(
+    "tests/data/columns/formulas.csv",
+    "tests/scratch/columns/formulas_short_result.csv",
+    "tests/scratch/columns/formulas_short.log",
+  );
+  assert!(converted.is_ok(), "{:?}", converted);
+  let statuses = fs::read_to_string("tests/scratch/columns/formulas_short.log").unwrap();
+  assert_eq!(statuses, "12\n12\n12\n12\n");
+
+  // a missing input is reported by its path
+  let missing = harness.convert_file(
+    "tests/data/columns/missing.csv",
+    output,
+    "tests/scratch/columns/formulas.log",
+  );
+  let message = missing.unwrap_err().to_string();
+  assert!(
+    message.contains("tests/data/columns/missing.csv"),
+    "{}",
+    message
+  );
+
+  // without a selected column, the TeX of each row is in its first column
+  harness.csv = CsvOptions::default();
+  let first_column = "tests/scratch/columns/formulas_first_result.csv";
+  let converted = harness.convert_file(
+    "tests/data/columns/formulas.csv",
+    first_column,
+    "tests/scratch/columns/formulas_first.log",
+  );
+  assert!(converted.is_ok(), "{:?}", converted);
+  harness.csv.tex_column = Some(Column::Index(0));
+  let column_zero = "tests/scratch/columns/formulas_zero_result.csv";
+  let converted = harness.convert_file(
+    "tests/data/columns/formulas.csv",
+    column_zero,
+    "tests/scratch/columns/formulas_zero.log",
+  );
+  assert!(converted.is_ok(), "{:?}", converted);
+  assert_eq!(
+    fs::read_to_string(first_column).unwrap(),
+    fs::read_to_string(column_zero).unwrap()
+  );
+}
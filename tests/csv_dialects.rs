@@ -0,0 +1,59 @@
+use latexml_runner::csv_format::{Column, CsvDialect, CsvOptions, Quoting};
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::fs;
+
+#[test]
+fn tsv_input_to_custom_output() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+  harness.csv = CsvOptions {
+    input: CsvDialect {
+      delimiter: b'\t',
+      quoting: Quoting::Never,
+      ..CsvDialect::default()
+    },
+    output: CsvDialect {
+      delimiter: b';',
+      quoting: Quoting::Always,
+      ..CsvDialect::default()
+    },
+    output_headers: true,
+    has_headers: true,
+    tex_column: Some(Column::Name("tex".to_string())),
+    passthrough_columns: vec![Column::Name("id".to_string())],
+  };
+
+  let output = "tests/scratch/columns/formulas_tsv_result.csv";
+  let converted = harness.convert_file(
+    "tests/data/columns/formulas.tsv",
+    output,
+    "tests/scratch/columns/formulas_tsv.log",
+  );
+  assert!(converted.is_ok(), "{:?}", converted);
+  let rows = csv::ReaderBuilder::new()
+    .delimiter(b';')
+    .from_path(output)
+    .unwrap()
+    .into_records()
+    .count();
+  assert_eq!(rows, 3);
+  let written = fs::read_to_string(output).unwrap();
+  assert!(
+    written.starts_with("\"id\";\"result\"\n\"1\";\""),
+    "{}",
+    written
+  );
+  // without input quoting, quotes are part of the passthrough id,
+  // which the output quotes, escaping its quotes by doubling them
+  assert!(written.contains("\n\"\"\"3\"\"\";\""), "{}", written);
+}
@@ -0,0 +1,52 @@
+use latexml_runner::csv_format::{Column, CsvOptions};
+use latexml_runner::Harness;
+use rand::prelude::*;
+
+#[test]
+fn malformed_rows_keep_their_place() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+  harness.csv = CsvOptions {
+    has_headers: true,
+    tex_column: Some("tex".parse().unwrap()),
+    passthrough_columns: vec![Column::Name("id".to_string())],
+    ..CsvOptions::default()
+  };
+  harness.failures_file = Some("tests/scratch/columns/malformed_failures.csv".to_string());
+
+  let output = "tests/scratch/columns/malformed_result.csv";
+  let log = "tests/scratch/columns/malformed.log";
+  let converted = harness.convert_file("tests/data/columns/malformed.csv", output, log);
+  assert!(converted.is_ok(), "{:?}", converted);
+  let rows: Vec<Vec<String>> = csv::ReaderBuilder::new()
+    .has_headers(false)
+    .from_path(output)
+    .unwrap()
+    .into_records()
+    .map(|record| record.unwrap().iter().map(str::to_string).collect())
+    .collect();
+  assert_eq!(rows.len(), 3);
+  assert_eq!(rows[0][0], "1");
+  assert_eq!(rows[1], vec!["", ""]);
+  assert_eq!(rows[2][0], "3");
+  let statuses: Vec<String> = std::fs::read_to_string(log)
+    .unwrap()
+    .lines()
+    .map(str::to_string)
+    .collect();
+  assert_eq!(statuses[1], "12");
+  assert!(statuses[2] != "12", "{:?}", statuses);
+
+  // malformed rows can't be fixed by a re-run
+  let failures = std::fs::read_to_string("tests/scratch/columns/malformed_failures.csv").unwrap();
+  assert_eq!(failures.trim(), "id,tex,url");
+}
@@ -0,0 +1,37 @@
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::fs;
+
+#[test]
+fn convert_document_tree() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "document"), ("format", "html5")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let harness = harness_result.unwrap();
+  assert_eq!(harness.document_extension, "html");
+
+  let output_dir = "tests/scratch/documents";
+  let log = "tests/scratch/documents.log";
+  let converted = harness.convert_documents("tests/data/documents", output_dir, log);
+  assert!(converted.is_ok(), "{:?}", converted);
+  let hello = fs::read_to_string(format!("{}/hello.html", output_dir)).unwrap();
+  assert!(hello.contains("Hello"), "{}", hello);
+  let square = fs::read_to_string(format!("{}/proofs/square.html", output_dir)).unwrap();
+  assert!(square.contains("induction"), "{}", square);
+  assert!(!std::path::Path::new(&format!("{}/proofs/refs.html", output_dir)).exists());
+  assert_eq!(
+    fs::read_to_string(log).unwrap(),
+    "hello.tex,0\nproofs/square.tex,0\n"
+  );
+
+  assert!(harness
+    .convert_documents("tests/data/documents/hello.tex", output_dir, log)
+    .is_err());
+}
@@ -0,0 +1,68 @@
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::fs;
+
+#[test]
+fn rerun_failures_in_place() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+
+  let dir = "tests/scratch/failures";
+  fs::create_dir_all(dir).unwrap();
+  let input = format!("{}/input.txt", dir);
+  let output = format!("{}/output.csv", dir);
+  let log = format!("{}/output.log", dir);
+  let failures = format!("{}/failures.txt", dir);
+  fs::write(&input, "a+b\n\\FAIL\nx^2\n\\FAIL\n").unwrap();
+  harness.failures_file = Some(failures.clone());
+  harness.failure_threshold = 1;
+
+  let converted = harness.convert_file(&input, &output, &log);
+  assert!(converted.is_ok(), "{:?}", converted);
+  assert_eq!(fs::read_to_string(&failures).unwrap(), "\\FAIL\n\\FAIL\n");
+
+  // fix one of the failed jobs, and merge the new results back into the original run
+  fs::write(&failures, "y^2\n\\FAIL\n").unwrap();
+  let rerun = harness.rerun_failures(&failures, &output, &log);
+  assert!(rerun.is_ok(), "{:?}", rerun);
+  assert_eq!(read_statuses(&log), vec![0, 0, 0, 2]);
+  // the job failing again is left in the failures file, for another re-run
+  assert_eq!(fs::read_to_string(&failures).unwrap(), "\\FAIL\n");
+
+  fs::write(&failures, "z^2\n").unwrap();
+  let rerun = harness.rerun_failures(&failures, &output, &log);
+  assert!(rerun.is_ok(), "{:?}", rerun);
+  assert_eq!(read_statuses(&log), vec![0, 0, 0, 0]);
+  assert_eq!(fs::read_to_string(&failures).unwrap(), "");
+  let results: Vec<_> = csv::ReaderBuilder::new()
+    .has_headers(false)
+    .from_path(&output)
+    .unwrap()
+    .into_records()
+    .map(|record| record.unwrap()[0].to_string())
+    .collect();
+  assert_eq!(results.len(), 4);
+  assert!(results[1].contains('y'), "{:?}", results);
+  assert!(results[3].contains('z'), "{:?}", results);
+
+  // a failures file not matching the original run is rejected
+  fs::write(&failures, "y^2\nz^2\n").unwrap();
+  assert!(harness.rerun_failures(&failures, &output, &log).is_err());
+}
+
+fn read_statuses(log: &str) -> Vec<u8> {
+  fs::read_to_string(log)
+    .unwrap()
+    .lines()
+    .map(|line| line.parse().unwrap())
+    .collect()
+}
@@ -0,0 +1,38 @@
+use latexml_runner::failure::FailureKind;
+use latexml_runner::retry::RetryPolicy;
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::time::{Duration, Instant};
+
+#[test]
+fn abandon_slow_jobs() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+  harness.retry_policy = RetryPolicy::never();
+  harness.job_timeout = Some(Duration::from_secs(2));
+
+  let start = Instant::now();
+  let responses = harness
+    .convert_iterator(["\\def\\HANG{\\HANG}\\HANG"].into_iter(), 1)
+    .unwrap();
+  assert!(start.elapsed() < Duration::from_secs(10));
+  assert_eq!(
+    responses[0].status_code,
+    FailureKind::Timeout.status_code(),
+    "{:?}",
+    responses[0]
+  );
+
+  // the server was respawned, and converts the following jobs
+  let responses = harness.convert_iterator(["a+b"].into_iter(), 2).unwrap();
+  assert!(responses[0].status_code < 3, "{:?}", responses[0]);
+}
@@ -19,7 +19,7 @@ fn runner_helper(input_file: &str, output_file: &str, log_file: &str) {
     .collect(),
   );
   assert!(harness_result.is_ok(), "{:?}", harness_result);
-  let mut harness = harness_result.unwrap();
+  let harness = harness_result.unwrap();
 
   let rel_input_file = format!("tests/data/{}", input_file);
   let rel_output_file = format!("tests/scratch/{}", output_file);
@@ -0,0 +1,30 @@
+use latexml_runner::metrics;
+use rand::prelude::*;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+#[test]
+fn export_metrics() {
+  let registry = metrics::global();
+  registry.observe_conversion(0, Duration::from_millis(20));
+  registry.observe_conversion(3, Duration::from_secs(200));
+  registry.inc_retries();
+  registry.inc_rotations();
+
+  let address = format!("127.0.0.1:{}", thread_rng().gen_range(20000, 25000));
+  let exporter = metrics::serve(&address);
+  assert!(exporter.is_ok(), "{:?}", exporter);
+  let mut stream = TcpStream::connect(&address).unwrap();
+  stream.write_all(b"GET /metrics HTTP/1.0\r\n\r\n").unwrap();
+  let mut response = String::new();
+  stream.read_to_string(&mut response).unwrap();
+
+  assert!(response.starts_with("HTTP/1.0 200 OK"), "{}", response);
+  assert!(response.contains("latexml_runner_conversions_total{status_code=\"0\"} 1"));
+  assert!(response.contains("latexml_runner_conversions_total{status_code=\"3\"} 1"));
+  assert!(response.contains("latexml_runner_conversion_seconds_bucket{le=\"0.025\"} 1"));
+  assert!(response.contains("latexml_runner_conversion_seconds_bucket{le=\"+Inf\"} 2"));
+  assert!(response.contains("latexml_runner_retries_total 1"));
+  assert!(response.contains("latexml_runner_port_rotations_total 1"));
+}
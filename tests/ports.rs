@@ -0,0 +1,22 @@
+use latexml_runner::ports::{self, PortAllocator};
+use rand::prelude::*;
+use std::net::TcpListener;
+
+#[test]
+fn allocate_unique_bindable_ports() {
+  let from_port: u16 = thread_rng().gen_range(20000, 30000);
+  let allocator = PortAllocator::new();
+  // a port held by someone else is skipped
+  let _listener = TcpListener::bind(("127.0.0.1", from_port)).unwrap();
+  assert!(!ports::is_bindable(from_port));
+
+  let first = allocator.allocate(from_port).unwrap();
+  let second = allocator.allocate(from_port).unwrap();
+  assert!(first > from_port);
+  assert!(second > first);
+  assert!(allocator.is_allocated(first));
+
+  allocator.release(first);
+  assert!(!allocator.is_allocated(first));
+  assert_eq!(allocator.allocate(from_port), Ok(first));
+}
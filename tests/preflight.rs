@@ -0,0 +1,26 @@
+use latexml_runner::Harness;
+use rand::prelude::*;
+
+#[test]
+fn report_missing_preloads() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [
+      ("whatsin", "math"),
+      ("whatsout", "math"),
+      ("preload", "nonexistent_package.sty"),
+    ]
+    .iter()
+    .map(|(x, y)| (x.to_string(), y.to_string()))
+    .collect(),
+  );
+  match harness_result {
+    Ok(_) => panic!("booted a harness with a missing preload"),
+    Err(e) => {
+      let message = e.to_string();
+      assert!(message.contains("nonexistent_package"), "{}", message);
+    },
+  }
+}
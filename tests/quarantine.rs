@@ -0,0 +1,92 @@
+mod common;
+
+use latexml_runner::quarantine::Quarantine;
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::fs;
+
+#[test]
+fn quarantine_poison_jobs() {
+  let path = "tests/scratch/quarantine/poison.csv";
+  let quarantine_result = Quarantine::with_file(2, path);
+  assert!(quarantine_result.is_ok(), "{:?}", quarantine_result);
+  let quarantine = quarantine_result.unwrap();
+  assert!(!quarantine.contains("\\crash"));
+  let added = quarantine.add(7, "took down a server 2 times", "\\crash");
+  assert!(added.is_ok(), "{:?}", added);
+  assert!(quarantine.contains("\\crash"));
+  assert!(!quarantine.contains("\\fine"));
+
+  let recorded = fs::read_to_string(path).unwrap();
+  assert_eq!(
+    recorded,
+    "line,reason,input\n7,took down a server 2 times,\\crash\n"
+  );
+}
+
+#[test]
+fn quarantine_jobs_crashing_servers() {
+  common::use_latexmls_double();
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  // several servers, so that both CRASH jobs may be in flight at the same time
+  let pool = rayon::ThreadPoolBuilder::new()
+    .num_threads(4)
+    .build()
+    .unwrap();
+  let harness_result = pool.install(|| {
+    Harness::new(
+      from_port,
+      0,
+      [("whatsin", "math"), ("whatsout", "math")]
+        .iter()
+        .map(|(x, y)| (x.to_string(), y.to_string()))
+        .collect(),
+    )
+    .map_err(|e| e.to_string())
+  });
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+
+  let dir = "tests/scratch/quarantine";
+  fs::create_dir_all(dir).unwrap();
+  let input = format!("{}/crashing.txt", dir);
+  let output = format!("{}/crashing_result.csv", dir);
+  let log = format!("{}/crashing.log", dir);
+  let quarantined = format!("{}/crashing_quarantine.csv", dir);
+  // the test double of latexmls exits mid-request on a CRASH job, taking its server down
+  fs::write(&input, "a+b\n\\CRASH\nx^2\n\\CRASH\n").unwrap();
+  harness.quarantine = Some(Quarantine::with_file(2, &quarantined).unwrap());
+
+  let converted = pool.install(|| {
+    harness
+      .convert_file(&input, &output, &log)
+      .map_err(|e| e.to_string())
+  });
+  assert!(converted.is_ok(), "{:?}", converted);
+  let mut statuses: Vec<String> = fs::read_to_string(&log)
+    .unwrap()
+    .lines()
+    .map(str::to_string)
+    .collect();
+  statuses.sort();
+  assert_eq!(statuses, vec!["0", "0", "11", "11"]);
+  // the poison job is recorded once, with its line and reason, whichever
+  // of its two occurrences got quarantined first
+  let records: Vec<Vec<String>> = csv::Reader::from_path(&quarantined)
+    .unwrap()
+    .into_records()
+    .map(|record| record.unwrap().iter().map(str::to_string).collect())
+    .collect();
+  assert_eq!(records.len(), 1, "{:?}", records);
+  assert!(
+    records[0][0] == "2" || records[0][0] == "4",
+    "{:?}",
+    records[0]
+  );
+  assert!(
+    records[0][1].starts_with("took down a server 2 times"),
+    "{:?}",
+    records[0]
+  );
+  assert_eq!(records[0][2], "\\CRASH");
+}
@@ -0,0 +1,54 @@
+use latexml_runner::failure::{ConversionError, FailureKind};
+use latexml_runner::retry::RetryPolicy;
+use latexml_runner::server::LatexmlResponse;
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+
+#[test]
+fn classify_failures() {
+  let refused: Box<dyn Error> = io::Error::from(io::ErrorKind::ConnectionRefused).into();
+  assert_eq!(
+    FailureKind::classify(refused.as_ref()),
+    FailureKind::ConnectionRefused
+  );
+  let timed_out: Box<dyn Error> = io::Error::from(io::ErrorKind::WouldBlock).into();
+  assert_eq!(
+    FailureKind::classify(timed_out.as_ref()),
+    FailureKind::Timeout
+  );
+  let malformed: Box<dyn Error> =
+    ConversionError::new(FailureKind::MalformedResponse, "expected value").into();
+  assert_eq!(
+    FailureKind::classify(malformed.as_ref()),
+    FailureKind::MalformedResponse
+  );
+  let other: Box<dyn Error> = "something else".into();
+  assert_eq!(FailureKind::classify(other.as_ref()), FailureKind::Other);
+
+  let response = LatexmlResponse::failure(FailureKind::Timeout, &"took too long");
+  assert_eq!(
+    FailureKind::from_status_code(response.status_code),
+    Some(FailureKind::Timeout)
+  );
+  assert_eq!(FailureKind::from_status_code(3), None);
+  assert_eq!("connection_lost".parse(), Ok(FailureKind::ConnectionLost));
+  assert!("nonsense".parse::<FailureKind>().is_err());
+}
+
+#[test]
+fn retry_with_backoff() {
+  let policy = RetryPolicy {
+    max_attempts: 4,
+    backoff: Duration::from_millis(100),
+    retry_on: vec![FailureKind::Timeout, FailureKind::ConnectionLost],
+    ..RetryPolicy::default()
+  };
+  assert!(policy.should_retry(FailureKind::Timeout, 1));
+  assert!(policy.should_retry(FailureKind::ConnectionLost, 3));
+  assert!(!policy.should_retry(FailureKind::ConnectionLost, 4));
+  assert!(!policy.should_retry(FailureKind::MalformedResponse, 1));
+  assert_eq!(policy.delay(1), Duration::from_millis(100));
+  assert_eq!(policy.delay(3), Duration::from_millis(400));
+  assert!(!RetryPolicy::never().should_retry(FailureKind::Timeout, 1));
+}
@@ -0,0 +1,32 @@
+use latexml_runner::{memory, metrics, Harness};
+use rand::prelude::*;
+use std::{process, thread, time::Duration};
+
+#[test]
+fn recycle_servers_over_memory_ceiling() {
+  // the runner itself is a process tree with resident memory
+  let own_memory = memory::tree_rss(process::id());
+  assert!(own_memory.unwrap_or(0) > 0, "{:?}", own_memory);
+
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+  // any latexmls process is larger than a kilobyte
+  harness.max_server_memory = Some(1024);
+
+  let rotations = metrics::global().rotations();
+  let first = harness.convert_one("a+b");
+  assert!(first.is_ok(), "{:?}", first);
+  thread::sleep(Duration::from_millis(1100));
+  let second = harness.convert_one("x^2");
+  assert!(second.is_ok(), "{:?}", second);
+  assert!(metrics::global().rotations() > rotations);
+}
@@ -0,0 +1,69 @@
+use latexml_runner::service::{self, ServiceOptions};
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::{thread, time};
+
+fn request(address: &str, head: &str, body: &str) -> String {
+  let mut stream = TcpStream::connect(address).unwrap();
+  write!(
+    stream,
+    "{}\r\nContent-Length: {}\r\n\r\n{}",
+    head,
+    body.len(),
+    body
+  )
+  .unwrap();
+  let mut response = String::new();
+  stream.read_to_string(&mut response).unwrap();
+  response
+}
+
+#[test]
+fn serve_conversions() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let harness = harness_result.unwrap();
+  let address = format!("127.0.0.1:{}", thread_rng().gen_range(16000, 20000));
+  let service_address = address.clone();
+  thread::spawn(move || {
+    service::serve(harness, &service_address, ServiceOptions::default()).unwrap();
+  });
+  thread::sleep(time::Duration::from_millis(200));
+
+  let health = request(&address, "GET /health HTTP/1.0", "");
+  assert!(health.starts_with("HTTP/1.0 200 OK"), "{}", health);
+
+  let single = request(&address, "POST /convert HTTP/1.0", "\"E=mc^2\"");
+  assert!(single.starts_with("HTTP/1.0 200 OK"), "{}", single);
+  assert!(single.contains("\"status_code\":0"), "{}", single);
+
+  let batch = request(
+    &address,
+    "POST /convert HTTP/1.0",
+    "[\"a+b\", \"\\\\sqrt{x}\"]",
+  );
+  assert!(batch.starts_with("HTTP/1.0 200 OK"), "{}", batch);
+  let body = &batch[batch.find("\r\n\r\n").unwrap() + 4..];
+  let responses: Vec<serde_json::Value> = serde_json::from_str(body).unwrap();
+  assert_eq!(responses.len(), 2);
+
+  let unreadable = request(
+    &address,
+    "POST /convert HTTP/1.0\r\nContent-Length: lots",
+    "",
+  );
+  assert!(unreadable.starts_with("HTTP/1.0 400"), "{}", unreadable);
+
+  let missing = request(&address, "GET /nowhere HTTP/1.0", "");
+  assert!(missing.starts_with("HTTP/1.0 404"), "{}", missing);
+}
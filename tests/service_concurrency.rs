@@ -0,0 +1,68 @@
+mod common;
+
+use latexml_runner::service::{self, ServiceOptions};
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+use std::{thread, time};
+
+fn request(address: &str, body: &str) -> String {
+  let mut stream = TcpStream::connect(address).unwrap();
+  write!(
+    stream,
+    "POST /convert HTTP/1.0\r\nContent-Length: {}\r\n\r\n{}",
+    body.len(),
+    body
+  )
+  .unwrap();
+  let mut response = String::new();
+  stream.read_to_string(&mut response).unwrap();
+  response
+}
+
+#[test]
+fn slow_requests_dont_hold_up_others() {
+  common::use_latexmls_double();
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  // two servers, one of which the slow request occupies
+  let pool = rayon::ThreadPoolBuilder::new()
+    .num_threads(2)
+    .build()
+    .unwrap();
+  let harness_result = pool.install(|| {
+    Harness::new(
+      from_port,
+      0,
+      [("whatsin", "math"), ("whatsout", "math")]
+        .iter()
+        .map(|(x, y)| (x.to_string(), y.to_string()))
+        .collect(),
+    )
+    .map_err(|e| e.to_string())
+  });
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let harness = harness_result.unwrap();
+  let address = format!("127.0.0.1:{}", thread_rng().gen_range(16000, 20000));
+  let service_address = address.clone();
+  thread::spawn(move || {
+    service::serve(harness, &service_address, ServiceOptions::default()).unwrap();
+  });
+  thread::sleep(time::Duration::from_millis(200));
+
+  // the test double holds on to a HANG job for 30 seconds
+  let slow_address = address.clone();
+  thread::spawn(move || request(&slow_address, "\"\\\\HANG\""));
+  thread::sleep(time::Duration::from_millis(500));
+
+  let start = Instant::now();
+  let fast = request(&address, "\"a+b\"");
+  assert!(fast.starts_with("HTTP/1.0 200 OK"), "{}", fast);
+  assert!(fast.contains("<m>a+b</m>"), "{}", fast);
+  assert!(
+    start.elapsed() < Duration::from_secs(10),
+    "{:?}",
+    start.elapsed()
+  );
+}
@@ -0,0 +1,38 @@
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn convert_from_many_threads() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let harness = Arc::new(harness_result.unwrap());
+
+  let workers: Vec<_> = (0..8)
+    .map(|index| {
+      let shared = harness.clone();
+      thread::spawn(move || {
+        if index % 2 == 0 {
+          shared.convert_one(&format!("x^{}", index))
+        } else {
+          shared.convert_one_timeout(&format!("y^{}", index), Duration::from_secs(60))
+        }
+        .map_err(|e| e.to_string())
+      })
+    })
+    .collect();
+  for worker in workers {
+    let result = worker.join().unwrap();
+    assert!(result.is_ok(), "{:?}", result);
+  }
+}
@@ -20,7 +20,7 @@ fn convert_file() {
     .collect(),
   );
   assert!(harness_result.is_ok(), "{:?}", harness_result);
-  let mut harness = harness_result.unwrap();
+  let harness = harness_result.unwrap();
   let result = harness.convert_dir(
     "tests/data/sample_dir",
     "tests/scratch/sample_dir",
@@ -0,0 +1,64 @@
+mod common;
+
+use latexml_runner::txt_format::{self, TxtOptions};
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::fs;
+
+fn statuses(log: &str) -> Vec<String> {
+  fs::read_to_string(log)
+    .unwrap()
+    .lines()
+    .map(str::to_string)
+    .collect()
+}
+
+#[test]
+fn undecodable_lines_keep_their_place() {
+  common::use_latexmls_double();
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+
+  let dir = "tests/scratch/encoding";
+  fs::create_dir_all(dir).unwrap();
+  let input = "tests/data/encoding/latin1.txt";
+  let output = format!("{}/latin1_result.csv", dir);
+  let log = format!("{}/latin1.log", dir);
+  let failures = format!("{}/latin1_failures.txt", dir);
+  harness.failures_file = Some(failures.clone());
+
+  // not valid UTF-8, so never sent to latexmls
+  let converted = harness.convert_file(input, &output, &log);
+  assert!(converted.is_ok(), "{:?}", converted);
+  assert_eq!(statuses(&log), vec!["0", "12", "0"]);
+  assert_eq!(fs::read_to_string(&failures).unwrap(), "");
+
+  // decoded via the configured encoding instead
+  harness.txt = TxtOptions {
+    encoding: txt_format::parse_encoding("latin1").unwrap(),
+    ..TxtOptions::default()
+  };
+  let converted = harness.convert_file(input, &output, &log);
+  assert!(converted.is_ok(), "{:?}", converted);
+  assert_eq!(statuses(&log), vec!["0", "0", "0"]);
+  // the test double of latexmls echoes the TeX it received
+  let results: Vec<String> = csv::ReaderBuilder::new()
+    .has_headers(false)
+    .from_path(&output)
+    .unwrap()
+    .into_records()
+    .map(|record| record.unwrap()[0].to_string())
+    .collect();
+  assert_eq!(results[1], "<m>café</m>");
+
+  assert!(txt_format::parse_encoding("utf-16").is_err());
+}
@@ -0,0 +1,108 @@
+use latexml_runner::txt_format::{self, Framing, TxtOptions};
+use latexml_runner::Harness;
+use rand::prelude::*;
+use std::fs;
+
+fn statuses(log: &str) -> Vec<String> {
+  fs::read_to_string(log)
+    .unwrap()
+    .lines()
+    .map(str::to_string)
+    .collect()
+}
+
+/// The line and text of each record of `input`, framed as per `framing`
+fn records(input: &str, framing: Framing) -> Vec<(usize, String)> {
+  let options = TxtOptions {
+    framing,
+    ..TxtOptions::default()
+  };
+  txt_format::records(input.as_bytes(), options)
+    .map(|record| {
+      let record = record.unwrap().unwrap();
+      (record.line, record.text)
+    })
+    .collect()
+}
+
+#[test]
+fn frame_txt_records() {
+  let record = |line: usize, text: &str| (line, text.to_string());
+  assert_eq!(
+    records("a+b\r\n\nx^2", Framing::Line),
+    vec![record(1, "a+b"), record(2, ""), record(3, "x^2")]
+  );
+  assert_eq!(
+    records("\na+b\n=c\n\n\n\\FAIL\nx\n\ny^2\n", Framing::BlankLine),
+    vec![
+      record(2, "a+b\n=c"),
+      record(6, "\\FAIL\nx"),
+      record(9, "y^2")
+    ]
+  );
+  // NUL terminators on lines of their own don't leave a line break in the next record,
+  // and make no records when repeated
+  assert_eq!(
+    records("a\n\nb\0\nc\0\n\0\nd\0\n", Framing::Nul),
+    vec![record(1, "a\n\nb"), record(4, "c"), record(6, "d")]
+  );
+  // leading and repeated separators, or blank lines after the last one, make no records
+  let separator = || Framing::Separator(String::from("%%"));
+  assert_eq!(
+    records("%%\na\n\nb\n%%\n%%\nc\n%%\n\n", separator()),
+    vec![record(2, "a\n\nb"), record(7, "c")]
+  );
+  assert_eq!(records("%%\n\n%%\n", separator()), vec![]);
+}
+
+#[test]
+fn multiline_txt_records() {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    0,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+
+  let dir = "tests/scratch/framing";
+  fs::create_dir_all(dir).unwrap();
+  let output = format!("{}/output.csv", dir);
+  let log = format!("{}/output.log", dir);
+  let failures = format!("{}/failures.txt", dir);
+  harness.failures_file = Some(failures.clone());
+  harness.failure_threshold = 1;
+
+  // blank-line separated paragraphs
+  let input = format!("{}/paragraphs.txt", dir);
+  fs::write(&input, "\na+b\n=c\n\n\n\\FAIL\nx\n\ny^2\n").unwrap();
+  harness.txt = TxtOptions {
+    framing: Framing::BlankLine,
+    ..TxtOptions::default()
+  };
+  let converted = harness.convert_file(&input, &output, &log);
+  assert!(converted.is_ok(), "{:?}", converted);
+  // three records, the failed one of which is written back with the same framing
+  assert_eq!(statuses(&log).len(), 3);
+  assert_eq!(fs::read_to_string(&failures).unwrap(), "\\FAIL\nx\n\n");
+
+  // NUL separated records may contain blank lines
+  let input = format!("{}/records.txt", dir);
+  fs::write(&input, "a\n\nb\0c\0").unwrap();
+  harness.txt.framing = Framing::Nul;
+  let converted = harness.convert_file(&input, &output, &log);
+  assert!(converted.is_ok(), "{:?}", converted);
+  assert_eq!(statuses(&log).len(), 2);
+
+  // custom separator lines
+  let input = format!("{}/separated.txt", dir);
+  fs::write(&input, "a\n\nb\n%%\nc\n%%\n").unwrap();
+  harness.txt.framing = Framing::Separator(String::from("%%"));
+  let converted = harness.convert_file(&input, &output, &log);
+  assert!(converted.is_ok(), "{:?}", converted);
+  assert_eq!(statuses(&log).len(), 2);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use latexml_runner::{metrics, Harness};
+use rand::prelude::*;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// the tests count server rotations, which are recorded globally
+static ROTATIONS: Mutex<()> = Mutex::new(());
+
+#[test]
+fn autoflush_with_warm_standby() {
+  let _rotations = ROTATIONS.lock().unwrap();
+  common::use_latexmls_double();
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  let harness_result = Harness::new(
+    from_port,
+    4,
+    [("whatsin", "math"), ("whatsout", "math")]
+      .iter()
+      .map(|(x, y)| (x.to_string(), y.to_string()))
+      .collect(),
+  );
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+  harness.warm_standby = true;
+
+  let rotations = metrics::global().rotations();
+  for index in 0..12 {
+    let result = harness.convert_one(&format!("x^{}", index));
+    assert!(result.is_ok(), "{:?}", result);
+  }
+  assert!(metrics::global().rotations() > rotations);
+}
+
+#[test]
+fn warm_standby_waits_for_the_limit() {
+  let _rotations = ROTATIONS.lock().unwrap();
+  common::use_latexmls_double();
+  // the job during which a server without a standby is recycled
+  let recycled = jobs_until_rotation(false, |_| ());
+  // a booted standby is held in reserve, rather than swapped in early, when given time to boot
+  let swapped = jobs_until_rotation(true, |index| {
+    if index + 4 >= recycled {
+      thread::sleep(Duration::from_secs(2));
+    }
+  });
+  assert_eq!(swapped, recycled);
+}
+
+/// Converts jobs with a fresh server, calling `after_job` with the index of each job,
+/// until that server is recycled, returning the index of the job which recycled it
+fn jobs_until_rotation(warm_standby: bool, after_job: impl Fn(usize)) -> usize {
+  let from_port: u16 = thread_rng().gen_range(11000, 16000);
+  // a pool of a single server, so that every job wears down the same server
+  let single = rayon::ThreadPoolBuilder::new()
+    .num_threads(1)
+    .build()
+    .unwrap();
+  let harness_result = single.install(|| {
+    Harness::new(
+      from_port,
+      60,
+      [("whatsin", "math"), ("whatsout", "math")]
+        .iter()
+        .map(|(x, y)| (x.to_string(), y.to_string()))
+        .collect(),
+    )
+    .map_err(|e| e.to_string())
+  });
+  assert!(harness_result.is_ok(), "{:?}", harness_result);
+  let mut harness = harness_result.unwrap();
+  harness.warm_standby = warm_standby;
+
+  let rotations = metrics::global().rotations();
+  for index in 0..100 {
+    let result = harness.convert_one(&format!("x^{}", index));
+    assert!(result.is_ok(), "{:?}", result);
+    assert_eq!(result.unwrap(), format!("<m>x^{}</m>", index));
+    if metrics::global().rotations() > rotations {
+      return index;
+    }
+    after_job(index);
+  }
+  panic!("the server was never recycled");
+}